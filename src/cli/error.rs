@@ -1,15 +1,34 @@
+use std::io;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub(crate) enum Error {
     #[error("No profile set")]
     NoProfileSet,
+    #[error("Can't read passphrase: {0}")]
+    Passphrase(#[source] io::Error),
+    #[error("Passphrases do not match")]
+    PassphraseMismatch,
+    #[error("Profile '{0}' has no readable ssh key")]
+    NoSshKey(String),
+    #[error("Can't read standard input: {0}")]
+    Stdin(#[source] io::Error),
+    #[error("Ssh host aliases aren't enabled")]
+    NoHostAliases,
+    #[cfg(not(unix))]
+    #[error("ssh-agent isn't supported on this platform")]
+    AgentUnsupported,
     #[error("{err}\nTip: {tip}")]
     WithTip { err: Box<dyn std::error::Error>, tip: &'static str },
     #[error(transparent)]
+    Doctor(#[from] crate::doctor::error::Error),
+    #[error(transparent)]
     Git(#[from] crate::git::error::Error),
     #[error(transparent)]
     Profile(#[from] crate::profile::error::Error),
     #[error(transparent)]
+    Settings(#[from] crate::settings::error::Error),
+    #[error(transparent)]
     Ssh(#[from] crate::ssh::error::Error),
 }
\ No newline at end of file
@@ -1,6 +1,11 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
+use clap::{Args, Parser, Subcommand};
+
+use crate::git::Scope;
 use crate::profile::model::Profile;
+use crate::settings::Store;
+use crate::ssh;
 use crate::ssh::key::r#type::KeyType;
 
 mod error;
@@ -26,22 +31,93 @@ pub(super) enum Cmd {
             .map_err(| e | format ! ("Can't read profile '{name}', cause:\n{e}"))
         )]
         profile: Profile,
-        /// Set the profile for global git config
+        #[command(flatten)]
+        scope: ScopeArgs,
+        /// Use the profile in every repository with a remote matching profile's remote url patterns,
+        /// instead of setting it in a single config
+        #[arg(short, long, conflicts_with = "scope")]
+        remotes: bool,
+        /// Also add profile's ssh key to the running ssh-agent, removing keys of other profiles from it
         #[arg(short, long)]
-        global: bool,
+        agent: bool,
+        /// Rewrite ssh remotes to profile's host aliases (see `g ssh-config`) with url.<alias>.insteadOf,
+        /// instead of choosing the key with core.sshCommand
+        #[arg(long, conflicts_with = "remotes")]
+        alias: bool,
     },
     /// Show currently set profile
-    #[clap(name = "whoami")]
+    /// Unless a scope is chosen, it's resolved from all git configs the same way git does it
+    #[clap(name = "whoami", verbatim_doc_comment)]
     WhoAmI {
-        /// Look up the profile in global config instead
-        #[arg(short, long)]
-        global: bool,
+        #[command(flatten)]
+        scope: ScopeArgs,
     },
     /// Manage profiles
     Profile {
         #[clap(subcommand)]
         command: ProfileCmd,
     },
+    /// Check profiles, their ssh keys, identity index and git configs for problems
+    Doctor {
+        /// Repair problems that can be repaired safely
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Manage ssh keys of profiles in the running ssh-agent
+    Agent {
+        #[clap(subcommand)]
+        command: AgentCmd,
+    },
+    /// Issue ssh certificates for profile keys
+    Ca {
+        #[clap(subcommand)]
+        command: CaCmd,
+    },
+    /// Manage host aliases of profiles in ssh config, for tools that run plain ssh
+    #[clap(name = "ssh-config")]
+    SshConfig {
+        #[clap(subcommand)]
+        command: SshConfigCmd,
+    },
+    /// Manage the identity index whoami uses to recognize profiles
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCmd,
+    },
+}
+
+#[derive(Args, Debug)]
+#[group(id = "scope", multiple = false)]
+pub(super) struct ScopeArgs {
+    /// Use config of current repository
+    #[arg(short, long)]
+    local: bool,
+    /// Use config of current worktree (config.worktree)
+    #[arg(short, long)]
+    worktree: bool,
+    /// Use global git config
+    #[arg(short, long)]
+    global: bool,
+    /// Use xdg git config ($XDG_CONFIG_HOME/git/config)
+    #[arg(short, long)]
+    xdg: bool,
+    /// Use system git config
+    #[arg(short, long)]
+    system: bool,
+}
+
+impl ScopeArgs {
+    fn scope(&self) -> Option<Scope> {
+        [
+            (self.local, Scope::Local),
+            (self.worktree, Scope::Worktree),
+            (self.global, Scope::Global),
+            (self.xdg, Scope::Xdg),
+            (self.system, Scope::System),
+        ]
+            .into_iter()
+            .find_map(|(set, scope)| set.then_some(scope))
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -57,6 +133,17 @@ pub(super) enum ProfileCmd {
         /// Name of the profile
         name: String,
     },
+    /// Print location of a profile's file, e.g. to edit it by hand
+    Path {
+        /// Name of the profile
+        name: String,
+    },
+    /// Convert all profiles to another file format and keep saving them in it
+    Convert {
+        /// File format to store profiles in
+        #[arg(value_enum)]
+        store: Store,
+    },
     /// Add a new profile
     Add {
         /// Name of the profile
@@ -68,18 +155,126 @@ pub(super) enum ProfileCmd {
         /// Override profile if exists
         #[arg(short, long)]
         force: bool,
-        /// Type of ssh key: dsa, rsa or ed255119 (default)
+        /// Remote url pattern to use the profile for, e.g. git@github.com:acme/** (can be repeated)
+        #[arg(short = 'R', long = "remote")]
+        remotes: Vec<String>,
+        /// Sign commits and tags with profile's signing key
+        #[arg(long)]
+        sign: bool,
+        /// OpenPGP key id to sign with instead of the ssh key
+        #[arg(long)]
+        gpg_key: Option<String>,
+        /// Existing OpenSSH private key to use instead of generating a new one
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Reference the --key where it is instead of copying it to ~/.ssh
+        #[arg(long, requires = "key")]
+        in_place: bool,
+        /// Encrypt the generated private key with a passphrase, prompted for if not given
+        #[arg(short, long, num_args = 0..=1, conflicts_with = "key")]
+        passphrase: Option<Option<String>>,
+        /// Allow generating dsa keys, which are refused by the key policy by default
+        #[arg(long, conflicts_with = "key")]
+        allow_dsa: bool,
+        /// Type of ssh key: dsa, rsa, ecdsa-p256, ecdsa-p384, ecdsa-p521 or ed25519 (default)
         /// To generate rsa key with specific size, use rsa<size>, e.g. rsa4096
         #[arg(
-            short, long, value_parser = KeyType::parse, default_value = "ed25519", verbatim_doc_comment
+            short, long, value_parser = KeyType::parse, default_value = "ed25519", verbatim_doc_comment,
+            conflicts_with = "key"
         )]
         key_type: KeyType,
     },
+    /// List profiles whose ssh keys violate the key policy
+    AuditKeys,
+    /// Use an existing OpenSSH private key as ssh key of a profile
+    ImportKey {
+        /// Name of the profile
+        name: String,
+        /// Path of the private key
+        path: PathBuf,
+        /// Replace keys the profile already has
+        #[arg(short, long, conflicts_with = "in_place")]
+        force: bool,
+        /// Reference the key where it is instead of copying it to ~/.ssh
+        #[arg(long)]
+        in_place: bool,
+    },
+    /// Replace ssh keys of a profile with new ones, keeping the previous keys as id_<name>.<timestamp>.old
+    RotateKey {
+        /// Name of the profile
+        name: String,
+        /// Encrypt the new private key with a passphrase, prompted for if not given
+        #[arg(short, long, num_args = 0..=1)]
+        passphrase: Option<Option<String>>,
+        /// Allow generating dsa keys, which are refused by the key policy by default
+        #[arg(long)]
+        allow_dsa: bool,
+        /// Type of ssh key: dsa, rsa, ecdsa-p256, ecdsa-p384, ecdsa-p521 or ed25519 (default)
+        /// To generate rsa key with specific size, use rsa<size>, e.g. rsa4096
+        #[arg(short, long, value_parser = KeyType::parse, default_value = "ed25519", verbatim_doc_comment)]
+        key_type: KeyType,
+    },
+    /// Manage known_hosts of a profile, used instead of yours once a host is added to it
+    KnownHosts {
+        #[clap(subcommand)]
+        command: KnownHostsCmd,
+    },
+    /// Use a profile automatically in every repository under a directory
+    Bind {
+        /// Name of the profile
+        #[arg(
+            value_parser = | name: & str | Profile::load(name)
+            .map_err(| e | format ! ("Can't read profile '{name}', cause:\n{e}"))
+        )]
+        profile: Profile,
+        /// Directory to bind the profile to
+        dir: PathBuf,
+    },
+    /// Remove binding of a directory
+    Unbind {
+        /// Directory to remove the binding from
+        dir: PathBuf,
+    },
     /// Remove an existing profile
     Remove {
         /// Name of the profile(s)
         profiles: Vec<String>,
     },
+    /// Rename a profile, along with its ssh keys, bindings and git configs using its keys
+    Rename {
+        /// Current name of the profile
+        old: String,
+        /// New name of the profile
+        new: String,
+    },
+    /// Create a new profile based on an existing one
+    /// Username, email and signing settings are copied, remote url patterns aren't
+    #[clap(verbatim_doc_comment)]
+    Clone {
+        /// Name of the profile to copy
+        source: String,
+        /// Name of the new profile
+        name: String,
+        /// Git username (user.name in gitconfig), if different than the copied one
+        #[arg(short, long)]
+        username: Option<String>,
+        /// Git user email (user.email in gitconfig), if different than the copied one
+        #[arg(short, long)]
+        email: Option<String>,
+        /// Copy ssh keys of the source profile instead of generating new ones
+        #[arg(long)]
+        keys: bool,
+        /// Allow generating dsa keys, which are refused by the key policy by default
+        #[arg(long)]
+        allow_dsa: bool,
+        /// Type of ssh key: dsa, rsa, ecdsa-p256, ecdsa-p384, ecdsa-p521 or ed25519 (default)
+        /// To generate rsa key with specific size, use rsa<size>, e.g. rsa4096
+        #[arg(
+            short, long, value_parser = KeyType::parse, default_value = "ed25519",
+            verbatim_doc_comment, conflicts_with = "keys"
+        )]
+        key_type: KeyType,
+    },
     /// Edit an existing profile
     Edit {
         /// Name of the profile
@@ -90,11 +285,32 @@ pub(super) enum ProfileCmd {
         /// Git user email (user.email in gitconfig)
         #[arg(short, long)]
         email: Option<String>,
-        /// Re-generate ssh keys
+        /// Add remote url pattern (can be repeated)
+        #[arg(long = "add-remote")]
+        add_remotes: Vec<String>,
+        /// Remove remote url pattern (can be repeated)
+        #[arg(long = "remove-remote")]
+        remove_remotes: Vec<String>,
+        /// Sign commits and tags with profile's signing key
+        #[arg(long)]
+        sign: Option<bool>,
+        /// OpenPGP key id to sign with instead of the ssh key
+        #[arg(long)]
+        gpg_key: Option<String>,
+        /// Sign with the ssh key again instead of the OpenPGP key
+        #[arg(long, conflicts_with = "gpg_key")]
+        no_gpg_key: bool,
+        /// Re-generate ssh keys, keeping the previous ones like rotate-key does
         #[arg(short, long)]
         regenerate: bool,
+        /// Encrypt the re-generated private key with a passphrase, prompted for if not given
+        #[arg(short, long, num_args = 0..=1, requires = "regenerate")]
+        passphrase: Option<Option<String>>,
+        /// Allow generating dsa keys, which are refused by the key policy by default
+        #[arg(long, requires = "regenerate")]
+        allow_dsa: bool,
         /// Applicable only if --regenerate is used
-        /// Type of ssh key: dsa, rsa or ed25519 (default)
+        /// Type of ssh key: dsa, rsa, ecdsa-p256, ecdsa-p384, ecdsa-p521 or ed25519 (default)
         /// To generate rsa key with specific size, use rsa<size>, e.g. rsa2048
         #[arg(
             short, long, value_parser = KeyType::parse, default_value = "ed25519",
@@ -103,3 +319,94 @@ pub(super) enum ProfileCmd {
         key_type: KeyType,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub(super) enum KnownHostsCmd {
+    /// Add host keys to profile's known_hosts, creating it if needed
+    Add {
+        /// Name of the profile
+        name: String,
+        /// File with entries in known_hosts format, e.g. saved output of `ssh-keyscan github.com`,
+        /// read from stdin if not given
+        #[clap(verbatim_doc_comment)]
+        file: Option<PathBuf>,
+    },
+    /// List host keys in profile's known_hosts
+    List {
+        /// Name of the profile
+        name: String,
+    },
+    /// Remove keys of a host from profile's known_hosts
+    Remove {
+        /// Name of the profile
+        name: String,
+        /// Host name as written in known_hosts, hashed host names can't be removed by name
+        host: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum AgentCmd {
+    /// Add ssh key of a profile to the agent, removing keys of other profiles from it
+    Load {
+        /// Name of the profile
+        #[arg(
+            value_parser = | name: & str | Profile::load(name)
+            .map_err(| e | format ! ("Can't read profile '{name}', cause:\n{e}"))
+        )]
+        profile: Profile,
+    },
+    /// Remove ssh keys of profiles from the agent, keys not belonging to any profile are kept
+    Unload {
+        /// Name of the profile to remove the key of, keys of all profiles are removed if not given
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum CaCmd {
+    /// Issue an OpenSSH user certificate for profile's ssh key, stored next to it as <key>-cert.pub
+    Sign {
+        /// Name of the profile
+        #[arg(
+            value_parser = | name: & str | Profile::load(name)
+            .map_err(| e | format ! ("Can't read profile '{name}', cause:\n{e}"))
+        )]
+        profile: Profile,
+        /// Private key of the certificate authority
+        #[arg(long)]
+        ca: PathBuf,
+        /// User names the certificate is valid for, comma separated or repeated
+        #[arg(short = 'n', long, value_delimiter = ',', required = true)]
+        principals: Vec<String>,
+        /// How long the certificate is valid since now, e.g. +52w, 30d or 1d12h
+        #[arg(short = 'V', long, value_parser = ssh::cert::parse_validity, default_value = "+52w")]
+        validity: u64,
+        /// Key identity, shown in server logs (profile's email by default)
+        #[arg(short = 'I', long)]
+        key_id: Option<String>,
+        /// Serial number of the certificate, e.g. to revoke it later
+        #[arg(short = 'z', long, default_value_t = 0)]
+        serial: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum SshConfigCmd {
+    /// Write `Host <host>-<profile>` blocks for every profile into ~/.ssh/config.d/g and include it in ~/.ssh/config
+    Enable {
+        /// Hosts to write aliases for
+        #[arg(default_value = "github.com")]
+        hosts: Vec<String>,
+    },
+    /// Remove ~/.ssh/config.d/g and its include from ~/.ssh/config
+    Disable,
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum CacheCmd {
+    /// Re-create the index from profile files
+    Rebuild,
+    /// Check that the index matches profile files
+    Verify,
+}
@@ -1,10 +1,20 @@
-use ssh_key::HashAlg;
+use std::{fs, io};
+use std::path::{Path, PathBuf};
 
-use crate::{git, profile, ssh};
-use crate::cli::{Cli, Cmd, ProfileCmd};
+use ssh_key::{HashAlg, PrivateKey};
+use ssh_key::known_hosts::Entry;
+
+use crate::{doctor, git, now, profile, ssh};
+use crate::git::{Mode, Scope};
+use crate::cli::{AgentCmd, CaCmd, CacheCmd, Cli, Cmd, KnownHostsCmd, ProfileCmd, SshConfigCmd};
 use crate::cli::error::Error;
 use crate::cli::Result;
-use crate::profile::model::Profile;
+use crate::profile::cache::Lookup;
+use crate::profile::model::{known_hosts_path, Profile};
+use crate::settings::Settings;
+#[cfg(unix)]
+use crate::ssh::agent::Agent;
+use crate::ssh::key::policy::Violation;
 use crate::ssh::key::r#type::{KeyType, RandomArtHeader};
 
 pub(crate) trait Presentation {
@@ -20,18 +30,234 @@ impl Presentation for Cli {
 impl Presentation for Cmd {
     fn present(self) -> Result<()> {
         match self {
-            Cmd::Su { profile, global } => {
-                git::configure_user(&profile, global)?;
+            Cmd::Su { profile, scope, remotes, agent, alias } => {
+                let scope = scope.scope().unwrap_or(Scope::Local);
+                let mode = if remotes { Mode::Includes } else { Mode::Keys(scope) };
+                let hosts = if alias { Settings::load()?.host_aliases } else { vec![] };
+                if alias && hosts.is_empty() {
+                    let err = Box::new(Error::NoHostAliases);
+                    Err(Error::WithTip { err, tip: "enable them with `g ssh-config enable [HOST]...`" })?
+                }
+                git::configure_user(&profile, mode)?;
+                if alias {
+                    git::alias_remotes(&profile, scope, &hosts)?;
+                }
+                if agent {
+                    load_into_agent(&profile)?;
+                }
             }
-            Cmd::WhoAmI { global } => {
-                let (username, email) = git::get_username_and_email(global)?;
-                let profile = profile::cache::get(&username, &email)
-                    .ok_or(Error::NoProfileSet)?;
+            Cmd::WhoAmI { scope } => {
+                let scope = scope.scope();
+                let binding = match scope {
+                    None | Some(Scope::Local | Scope::Worktree) => git::include::matching()?,
+                    _ => None,
+                };
+                let identity = match scope {
+                    Some(scope) => git::get_username_and_email(scope),
+                    None => git::get_effective_username_and_email(),
+                };
+                let profile = match identity {
+                    Ok((username, email)) => match profile::cache::lookup(&username, &email) {
+                        Lookup::Exact(name) => name,
+                        Lookup::SameEmail(names) => {
+                            eprintln!(
+                                "Warning: {email} is used by profile(s) '{}' with a different username than '{username}'",
+                                names.join("', '")
+                            );
+                            Err(Error::NoProfileSet)?
+                        }
+                        Lookup::Unknown => Err(Error::NoProfileSet)?,
+                    },
+                    Err(err) => binding.as_ref()
+                        .map(|b| b.profile_name.to_string())
+                        .ok_or(err)?,
+                };
                 println!("{profile}");
+                if let Some(binding) = binding {
+                    println!("Matched binding: includeIf \"{}\" -> '{}'", binding.condition, binding.profile_name);
+                }
             }
             Cmd::Profile { command } => {
                 command.present()?;
             }
+            Cmd::Agent { command } => {
+                command.present()?;
+            }
+            Cmd::Ca { command } => {
+                command.present()?;
+            }
+            Cmd::SshConfig { command } => {
+                command.present()?;
+            }
+            Cmd::Cache { command } => {
+                command.present()?;
+            }
+            Cmd::Doctor { fix } => {
+                let findings = doctor::check()?;
+                let mut remaining = 0;
+                let mut fixable = false;
+                for finding in &findings {
+                    if fix && finding.is_fixable() {
+                        doctor::fix(finding)?;
+                        println!("fixed: {finding}");
+                    } else {
+                        println!("{finding}");
+                        remaining += 1;
+                        fixable |= finding.is_fixable();
+                    }
+                }
+                if remaining > 0 {
+                    let err = Box::new(doctor::error::Error::ProblemsFound(remaining));
+                    let tip = if fixable {
+                        "re-run with --fix to repair what can be repaired safely"
+                    } else {
+                        "the remaining problems have to be resolved manually"
+                    };
+                    Err(Error::WithTip { err, tip })?
+                }
+                if findings.is_empty() {
+                    println!("No problems found");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Presentation for KnownHostsCmd {
+    fn present(self) -> Result<()> {
+        match self {
+            KnownHostsCmd::Add { name, file } => {
+                let profile = Profile::load(&name)?;
+                let content = match file {
+                    Some(path) => fs::read_to_string(&path).map_err(|e| ssh::error::Error::Io(e, path))?,
+                    None => io::read_to_string(io::stdin()).map_err(Error::Stdin)?,
+                };
+                let entries = ssh::known_hosts::parse(&content)?;
+                let created = profile.known_hosts().is_none();
+                let added = ssh::known_hosts::add(Path::new(&known_hosts_path(&name)), entries)?;
+                added.iter().for_each(|entry| println!("added: {}", describe_known_host(entry)));
+                if created {
+                    git::include::refresh(&profile)?;
+                    profile::aliases::regenerate()?;
+                    println!("'{name}' uses its own known_hosts now, re-run `g su {name}` where it's been set before");
+                }
+            }
+            KnownHostsCmd::List { name } => {
+                let profile = Profile::load(&name)?;
+                match profile.known_hosts() {
+                    Some(path) => ssh::known_hosts::read(Path::new(&path))?
+                        .iter()
+                        .for_each(|entry| println!("{}", describe_known_host(entry))),
+                    None => println!("'{name}' has no known_hosts of its own, ssh uses yours"),
+                }
+            }
+            KnownHostsCmd::Remove { name, host } => {
+                Profile::load(&name)?;
+                let removed = ssh::known_hosts::remove(Path::new(&known_hosts_path(&name)), &host)?;
+                if removed.is_empty() {
+                    Err(ssh::error::Error::UnknownHost(host))?
+                }
+                removed.iter().for_each(|entry| println!("removed: {}", describe_known_host(entry)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Presentation for AgentCmd {
+    fn present(self) -> Result<()> {
+        match self {
+            AgentCmd::Load { profile } => {
+                load_into_agent(&profile)?;
+            }
+            AgentCmd::Unload { name } => {
+                let mut agent = connect_agent()?;
+                unload_from_agent(&mut agent, |profile| name.as_ref().is_none_or(|name| profile.name == *name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+impl Presentation for AgentCmd {
+    fn present(self) -> Result<()> {
+        Err(Error::AgentUnsupported)
+    }
+}
+
+impl Presentation for CaCmd {
+    fn present(self) -> Result<()> {
+        match self {
+            CaCmd::Sign { profile, ca, principals, validity, key_id, serial } => {
+                let public = profile.read_public_key().ok_or_else(|| Error::NoSshKey(profile.name.clone()))?;
+                let ca = read_private_key(&ca.to_string_lossy())?;
+                let request = ssh::cert::Request {
+                    key_id: key_id.unwrap_or_else(|| profile.email.clone()),
+                    principals,
+                    serial,
+                    issued: now(),
+                    validity,
+                };
+                let certificate = ssh::cert::sign(&public, &ca, &request)?;
+                let path = profile.certificate();
+                ssh::cert::write(Path::new(&path), &certificate)?;
+                println!("Certificate written to {path}");
+                println!("{}", Profile::load(&profile.name)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Presentation for SshConfigCmd {
+    fn present(self) -> Result<()> {
+        let mut settings = Settings::load()?;
+        match self {
+            SshConfigCmd::Enable { hosts } => {
+                settings.host_aliases = hosts;
+                settings.save()?;
+                profile::aliases::regenerate()?;
+                if ssh::config::include()? {
+                    println!("Included {} in ~/.ssh/config", ssh::config::INCLUDE);
+                }
+                println!("Wrote host aliases to {}", ssh::config::path().display());
+                for host in &settings.host_aliases {
+                    println!("e.g. git clone git@{}:<repository>", ssh::config::alias(host, "<profile>"));
+                }
+            }
+            SshConfigCmd::Disable => {
+                settings.host_aliases.clear();
+                settings.save()?;
+                profile::aliases::regenerate()?;
+                if ssh::config::exclude()? {
+                    println!("Removed include of {} from ~/.ssh/config", ssh::config::INCLUDE);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Presentation for CacheCmd {
+    fn present(self) -> Result<()> {
+        match self {
+            CacheCmd::Rebuild => {
+                profile::cache::rebuild()?
+                    .iter()
+                    .for_each(|name| println!("indexed: {name}"));
+            }
+            CacheCmd::Verify => {
+                let issues = profile::cache::verify()?;
+                if !issues.is_empty() {
+                    issues.iter().for_each(|issue| println!("{issue}"));
+                    let err = Box::new(profile::error::Error::IndexOutdated(issues.len()));
+                    Err(Error::WithTip { err, tip: "run `g cache rebuild` to re-index profiles" })?
+                }
+                println!("Index is up to date");
+            }
         }
         Ok(())
     }
@@ -49,48 +275,342 @@ impl Presentation for ProfileCmd {
                     });
             }
             ProfileCmd::Show { name } => {
-                println!("{}", Profile::load(&name)?);
+                let profile = Profile::load(&name)?;
+                println!("{profile}");
+                let key_age = match profile.key_age_days() {
+                    Some(days) => format!("{days} day(s)"),
+                    None => "unknown".to_string(),
+                };
+                let rotations = match profile.rotated.len() {
+                    0 => String::new(),
+                    count => format!(", rotated {count} time(s)"),
+                };
+                println!("key age:        {key_age}{rotations}");
+                if let Some(certificate) = profile.read_certificate() {
+                    println!("certificate:    {}", ssh::cert::describe(&certificate, now()));
+                }
+            }
+            ProfileCmd::Path { name } => {
+                println!("{}", profile::path(&name)?);
             }
-            ProfileCmd::Add { name, username, email, force, key_type } => {
-                let profile = Profile::new(&name, &username, &email)?;
+            ProfileCmd::Convert { store } => {
+                profile::convert(store)?
+                    .iter()
+                    .for_each(|name| println!("converted: {name}"));
+            }
+            ProfileCmd::Add {
+                name, username, email, force, remotes, sign, gpg_key, key, in_place, passphrase, allow_dsa, key_type
+            } => {
+                let passphrase = read_passphrase(passphrase)?;
+                let mut profile = Profile::new(&name, &username, &email)?;
+                profile.remotes = remotes;
+                profile.sign = sign;
+                profile.gpg_key = gpg_key;
+                if let (true, Some(path)) = (in_place, &key) {
+                    profile.key = Some(adopt_ssh_key(&email, path)?);
+                }
                 println!("Writing profile...");
-                profile.save(false).map_err(|err| {
+                profile.clone().save(false).map_err(|err| {
                     let err = Box::new(err);
                     Error::WithTip { err, tip: "re-run with --force to overwrite" }
                 })?;
-                ssh::try_regenerate_pair(&name, &email, force).map_err(|err| {
-                    let err = Box::new(err);
-                    Error::WithTip { err, tip: "re-run with --force to re-generate" }
-                })?;
-                generate_ssh_keys(&name, &email, &key_type)?;
+                match key {
+                    Some(_) if in_place => {}
+                    Some(path) => import_ssh_key(&name, &email, &path, force)?,
+                    None => {
+                        ssh::try_regenerate_pair(&name, &email, force).map_err(|err| {
+                            let err = Box::new(err);
+                            Error::WithTip { err, tip: "re-run with --force to re-generate" }
+                        })?;
+                        generate_ssh_keys(&profile, &key_type, passphrase.as_deref(), allow_dsa, false)?;
+                    }
+                }
+                regenerate_shared_files()?;
+            }
+            ProfileCmd::AuditKeys => {
+                let violations = profile::audit_keys()?;
+                if !violations.is_empty() {
+                    violations.iter().for_each(|(name, violation)| println!("{name}: {violation}"));
+                    let err = Box::new(ssh::error::Error::PolicyViolations(violations.len()));
+                    Err(Error::WithTip { err, tip: "re-generate them with `g profile edit <name> --regenerate`" })?
+                }
+                println!("All keys follow the key policy");
+            }
+            ProfileCmd::ImportKey { name, path, force, in_place } => {
+                let profile = Profile::load(&name)?;
+                let key = if in_place {
+                    Some(adopt_ssh_key(&profile.email, &path)?)
+                } else {
+                    import_ssh_key(&profile.name, &profile.email, &path, force)?;
+                    None
+                };
+                // resets key age even if the path stays the same
+                let profile = profile::edit_key(&name, key)?;
+                git::include::refresh(&profile)?;
+                regenerate_shared_files()?;
+            }
+            ProfileCmd::RotateKey { name, passphrase, allow_dsa, key_type } => {
+                let passphrase = read_passphrase(passphrase)?;
+                // a key referenced in place isn't retired, the new one is generated in ~/.ssh
+                let (profile, referenced) = profile::detach_key(&name)?;
+                if let Some(key) = referenced {
+                    git::include::refresh(&profile)?;
+                    println!("{} is referenced in place, it's left where it is", key.display());
+                }
+                generate_ssh_keys(&profile, &key_type, passphrase.as_deref(), allow_dsa, true)?;
+                regenerate_shared_files()?;
+            }
+            ProfileCmd::KnownHosts { command } => {
+                command.present()?;
+            }
+            ProfileCmd::Bind { profile, dir } => {
+                let dir = git::include::bind(&profile, &dir)?;
+                println!("Bound '{}' to {}", profile.name, dir.display());
+            }
+            ProfileCmd::Unbind { dir } => {
+                git::include::unbind(&dir)?;
             }
             ProfileCmd::Remove { profiles } => {
                 for name in &profiles {
                     profile::remove(name)?;
+                    git::include::unbind_profile(name)?;
+                }
+                regenerate_shared_files()?;
+            }
+            ProfileCmd::Rename { old, new } => {
+                let profile = profile::rename(&old, &new)?;
+                let changed = match rename_in_git(&old, &profile) {
+                    Ok(changed) => changed,
+                    Err(err) => {
+                        // best effort, the original error is what matters
+                        if let Ok(renamed_back) = profile::rename(&new, &old) {
+                            let _ = rename_in_git(&new, &renamed_back);
+                        }
+                        return Err(err);
+                    }
+                };
+                for config in changed {
+                    println!("updated: {}", config.display());
                 }
+                regenerate_shared_files()?;
+                println!("Renamed '{old}' to '{new}'");
             }
-            ProfileCmd::Edit { name, username, email, regenerate, key_type } => {
+            ProfileCmd::Clone { source, name, username, email, keys, allow_dsa, key_type } => {
+                let profile = profile::clone(&source, &name, username, email, keys).map_err(|err| match err {
+                    err @ profile::error::Error::CombinationExists { .. } => {
+                        Error::WithTip { err: Box::new(err), tip: "pass a different --username or --email" }
+                    }
+                    err => err.into(),
+                })?;
+                if !keys {
+                    generate_ssh_keys(&profile, &key_type, None, allow_dsa, false)?;
+                }
+                regenerate_shared_files()?;
+            }
+            ProfileCmd::Edit {
+                name, username, email, add_remotes, remove_remotes, sign, gpg_key, no_gpg_key, regenerate, passphrase,
+                allow_dsa, key_type
+            } => {
+                let passphrase = read_passphrase(passphrase)?;
                 profile::edit(&name, username, email)?;
+                profile::edit_remotes(&name, add_remotes, remove_remotes)?;
+                let gpg_key = if no_gpg_key { Some(None) } else { gpg_key.map(Some) };
+                profile::edit_signing(&name, sign, gpg_key)?;
+                if regenerate {
+                    // new keys are always generated in ~/.ssh
+                    profile::edit_key(&name, None)?;
+                }
+                let profile = Profile::load(&name)?;
+                git::include::refresh(&profile)?;
                 if regenerate {
-                    let profile = Profile::load(&name)?;
-                    generate_ssh_keys(&profile.name, &profile.email, &key_type)?;
+                    generate_ssh_keys(&profile, &key_type, passphrase.as_deref(), allow_dsa, true)?;
                 }
+                // email is the signer's principal, so it has to be re-generated even without new keys
+                regenerate_shared_files()?;
             }
         }
         Ok(())
     }
 }
 
-fn generate_ssh_keys(profile_name: &str, email: &str, key_type: &KeyType) -> Result<()> {
-    let (private, public) = ssh::key::pair(email, key_type)?;
+/// Host patterns, algorithm and fingerprint of a known_hosts entry, prefixed with its marker if it has one
+fn describe_known_host(entry: &Entry) -> String {
+    let key = entry.public_key();
+    let description = format!(
+        "{} {} {}", entry.host_patterns().to_string(), key.algorithm().as_str(), key.fingerprint(HashAlg::Sha256)
+    );
+    match entry.marker() {
+        Some(marker) => format!("{marker} {description}"),
+        None => description,
+    }
+}
+
+/// Moves bindings of profile `old` to `profile` renamed from it and points git configs using its ssh keys
+/// and known_hosts at the renamed ones, returns paths of configs that changed
+fn rename_in_git(old: &str, profile: &Profile) -> Result<Vec<PathBuf>> {
+    git::include::rename(old, profile)?;
+
+    Ok(git::rename_keys(old, profile)?)
+}
+
+/// Re-writes files generated from all profiles: allowed signers and ssh host aliases
+fn regenerate_shared_files() -> Result<()> {
+    profile::signers::regenerate()?;
+    profile::aliases::regenerate()?;
+
+    Ok(())
+}
+
+/// Generates new ssh keys of `profile`, with `rotate` the previous ones are kept instead of being overwritten
+fn generate_ssh_keys(
+    profile: &Profile, key_type: &KeyType, passphrase: Option<&str>, allow_dsa: bool, rotate: bool,
+) -> Result<()> {
+    let policy = Settings::load()?.key_policy;
+    let policy = if allow_dsa { policy.allow_dsa() } else { policy };
+    let (private, public) = ssh::key::pair(&profile.email, key_type, &policy).map_err(|err| match err {
+        err @ ssh::error::Error::PolicyViolation(Violation::NotAllowed(_)) if *key_type == KeyType::Dsa => {
+            Error::WithTip { err: Box::new(err), tip: "dsa is disabled in modern OpenSSH, pass --allow-dsa to use it anyway" }
+        }
+        err => err.into(),
+    })?;
     println!("Generating ssh-{key_type} key pair...");
-    ssh::key::write_private(profile_name, &private)?;
-    ssh::key::write_public(profile_name, &public)?;
+    let private_path = profile.private_key();
+    let created = now();
+    let certified = Path::new(&profile.certificate()).exists();
+    if rotate {
+        let previous = profile.read_public_key();
+        if let Some(retired) = ssh::key::retire(Path::new(&private_path), created)? {
+            println!("Previous key kept as {}", retired.display());
+            if let Some(previous) = previous {
+                println!("Previous key fingerprint is: {}", previous.fingerprint(HashAlg::Sha256));
+            }
+        }
+    }
+    match passphrase {
+        Some(passphrase) => ssh::key::write_private_file(Path::new(&private_path), &ssh::key::encrypt(&private, passphrase)?)?,
+        None => ssh::key::write_private_file(Path::new(&private_path), &private)?,
+    }
+    ssh::key::write_public_file(Path::new(&profile.public_key()), &public)?;
+    profile::record_key(&profile.name, created, rotate)?;
     println!("Keys written");
     let fingerprint = private.fingerprint(HashAlg::Sha256);
     let random_art = fingerprint.to_randomart(&key_type.random_art_header());
     println!("Key fingerprint is: {fingerprint}");
     println!("The key's randomart image is:\n{random_art}");
+    if rotate {
+        println!("Register {} with your remotes before removing the previous key from them", profile.public_key());
+        if certified {
+            println!("The previous certificate was kept along, issue one for the new key with `g ca sign {}`", profile.name);
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(unix)]
+fn connect_agent() -> Result<Agent> {
+    Agent::connect().map_err(|err| match err {
+        err @ ssh::error::Error::AgentNotRunning => {
+            Error::WithTip { err: Box::new(err), tip: "start one with `eval $(ssh-agent)`" }
+        }
+        err => err.into(),
+    })
+}
+
+/// Adds ssh key of `profile` to the running ssh-agent, so that the agent can't offer a key of another profile first,
+/// their keys are removed from it
+#[cfg(unix)]
+fn load_into_agent(profile: &Profile) -> Result<()> {
+    let mut agent = connect_agent()?;
+    unload_from_agent(&mut agent, |other| other.name != profile.name)?;
+    let path = profile.private_key();
+    let private = read_private_key(&path)?;
+    let loaded = agent.identities()?
+        .iter()
+        .any(|identity| identity.key_data() == private.public_key().key_data());
+    if !loaded {
+        agent.add(&private, &path)?;
+    }
+    println!("Loaded {path} into ssh-agent");
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn load_into_agent(_profile: &Profile) -> Result<()> {
+    Err(Error::AgentUnsupported)
+}
+
+/// Reads private key at `path`, asking for its passphrase if it's encrypted
+fn read_private_key(path: &str) -> Result<PrivateKey> {
+    let private = ssh::key::read_private_file(Path::new(path))?;
+    if !private.is_encrypted() {
+        return Ok(private);
+    }
+    let passphrase = rpassword::prompt_password(format!("Enter passphrase for {path}: "))
+        .map_err(Error::Passphrase)?;
+
+    Ok(ssh::key::decrypt(&private, &passphrase)?)
+}
+
+/// Removes keys of profiles matching `filter` from the agent
+#[cfg(unix)]
+fn unload_from_agent(agent: &mut Agent, filter: impl Fn(&Profile) -> bool) -> Result<()> {
+    let keys = profile::list()?
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .filter(filter)
+        .filter_map(|profile| Some((profile.private_key(), profile.read_public_key()?)))
+        .collect::<Vec<_>>();
+    for identity in agent.identities()? {
+        if let Some((path, _)) = keys.iter().find(|(_, key)| key.key_data() == identity.key_data()) {
+            agent.remove(identity.key_data())?;
+            println!("Removed {path} from ssh-agent");
+        }
+    }
+
+    Ok(())
+}
+
+/// Passphrase given with `--passphrase`, or prompted for when the flag has no value.
+/// Empty passphrase means the key is left unencrypted, like with `ssh-keygen`.
+fn read_passphrase(arg: Option<Option<String>>) -> Result<Option<String>> {
+    let passphrase = match arg {
+        None => return Ok(None),
+        Some(Some(passphrase)) => passphrase,
+        Some(None) => {
+            let passphrase = rpassword::prompt_password("Enter passphrase (empty for no passphrase): ")
+                .map_err(Error::Passphrase)?;
+            let confirmation = rpassword::prompt_password("Enter same passphrase again: ")
+                .map_err(Error::Passphrase)?;
+            if passphrase != confirmation {
+                Err(Error::PassphraseMismatch)?
+            }
+            passphrase
+        }
+    };
+
+    Ok(Some(passphrase).filter(|p| !p.is_empty()))
+}
+
+fn import_ssh_key(profile_name: &str, email: &str, path: &Path, force: bool) -> Result<()> {
+    println!("Importing ssh key {}...", path.display());
+    let private = ssh::key::import(profile_name, email, path, force).map_err(|err| match err {
+        err @ ssh::error::Error::KeyPairExists => {
+            Error::WithTip { err: Box::new(err), tip: "re-run with --force to replace existing keys" }
+        }
+        err => err.into(),
+    })?;
+    println!("Keys written");
+    println!("Key fingerprint is: {}", private.fingerprint(HashAlg::Sha256));
+
+    Ok(())
+}
+
+fn adopt_ssh_key(email: &str, path: &Path) -> Result<PathBuf> {
+    let (path, private) = ssh::key::adopt(path, email)?;
+    println!("Using ssh key {} in place", path.display());
+    println!("Key fingerprint is: {}", private.fingerprint(HashAlg::Sha256));
+
+    Ok(path)
+}
@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum Error {
+    #[error("Found {0} problem(s)")]
+    ProblemsFound(usize),
+    #[error(transparent)]
+    Git(#[from] crate::git::error::Error),
+    #[error(transparent)]
+    Profile(#[from] crate::profile::error::Error),
+    #[error(transparent)]
+    Ssh(#[from] crate::ssh::error::Error),
+}
@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ssh_key::{PrivateKey, PublicKey};
+
+use crate::profile::cache::Issue;
+use crate::profile::model::Profile;
+use crate::{git, profile, ssh};
+
+pub mod error;
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Names of keys ssh-keygen creates by default, these aren't expected to belong to any profile
+const DEFAULT_KEYS: [&str; 7] = ["dsa", "ecdsa", "ecdsa_sk", "ed25519", "ed25519_sk", "rsa", "xmss"];
+
+/// Problem found by [`check`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// Key in `~/.ssh` named like a profile key, but no profile uses it
+    OrphanedKey(PathBuf),
+    /// Profile has neither private nor public key
+    MissingKeys(String),
+    /// Profile has private key, but no public one
+    MissingPublicKey(String),
+    /// Profile's public key doesn't match its private key
+    MismatchedPublicKey(String),
+    /// Private key can be read by other users
+    BadPermissions { path: PathBuf, mode: u32 },
+    /// Identity index doesn't match profile files
+    Index(Issue),
+    /// `core.sshCommand` refers to a key that doesn't exist
+    SshCommandMissingKey { config: PathBuf, key: String },
+}
+
+impl Finding {
+    /// Whether [`fix`] can repair the problem without losing anything
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Self::MissingPublicKey(_) | Self::MismatchedPublicKey(_) | Self::BadPermissions { .. } => true,
+            Self::Index(issue) => matches!(issue, Issue::Missing { .. } | Issue::Stale { .. }),
+            Self::OrphanedKey(_) | Self::MissingKeys(_) | Self::SshCommandMissingKey { .. } => false,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OrphanedKey(path) => write!(f, "orphaned key: {} doesn't belong to any profile", path.display()),
+            Self::MissingKeys(name) => write!(f, "missing keys: profile '{name}' has no ssh keys"),
+            Self::MissingPublicKey(name) => write!(f, "missing public key: profile '{name}' has only private key"),
+            Self::MismatchedPublicKey(name) => {
+                write!(f, "mismatched public key: public key of profile '{name}' doesn't match its private key")
+            }
+            Self::BadPermissions { path, mode } => {
+                write!(f, "bad permissions: {} is {mode:o}, should be 600", path.display())
+            }
+            Self::Index(issue) => write!(f, "identity index: {issue}"),
+            Self::SshCommandMissingKey { config, key } => {
+                write!(f, "missing key: core.sshCommand in {} uses {key}", config.display())
+            }
+        }
+    }
+}
+
+/// Cross-checks profile files, identity index, profiles' ssh keys and `core.sshCommand` in git configs
+///
+/// ```
+/// for finding in check().expect("Can't check profiles") {
+///     println!("{finding}");
+/// }
+/// ```
+pub fn check() -> Result<Vec<Finding>> {
+    // unreadable profiles are reported by the index check
+    let profiles = profile::list()?
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .collect::<Vec<_>>();
+    let mut findings = vec![];
+    for profile in &profiles {
+        findings.extend(check_keys(profile));
+    }
+    let used = profiles.iter()
+        .map(|profile| PathBuf::from(profile.private_key()))
+        .collect();
+    findings.extend(orphaned_keys(&used));
+    findings.extend(profile::cache::verify()?.into_iter().map(Finding::Index));
+    findings.extend(missing_command_keys()?);
+
+    Ok(findings)
+}
+
+/// Repairs the problem if it [`Finding::is_fixable`], does nothing otherwise
+///
+/// ```
+/// for finding in check().expect("Can't check profiles") {
+///     fix(&finding).expect(&format!("Can't fix {finding}"));
+/// }
+/// ```
+pub fn fix(finding: &Finding) -> Result<()> {
+    match finding {
+        Finding::MissingPublicKey(name) | Finding::MismatchedPublicKey(name) => {
+            let profile = Profile::load(name)?;
+            let public = ssh::key::public_from_private_file(Path::new(&profile.private_key()), &profile.email)?;
+            ssh::key::write_public_file(Path::new(&profile.public_key()), &public)?;
+        }
+        Finding::BadPermissions { path, .. } => ssh::key::restrict_permissions(path)?,
+        Finding::Index(Issue::Missing { .. } | Issue::Stale { .. }) => {
+            profile::cache::rebuild()?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn check_keys(profile: &Profile) -> Vec<Finding> {
+    let name = &profile.name;
+    let private_path = profile.private_key();
+    let public_path = profile.public_key();
+    let private = Path::new(&private_path);
+    let public = Path::new(&public_path);
+    if !private.exists() {
+        return if public.exists() { vec![] } else { vec![Finding::MissingKeys(name.to_string())] };
+    }
+    let mut findings = vec![];
+    if let Some(mode) = private_permissions(private) {
+        findings.push(Finding::BadPermissions { path: private.to_path_buf(), mode });
+    }
+    if !public.exists() {
+        findings.push(Finding::MissingPublicKey(name.to_string()));
+    } else if let (Ok(private), Ok(public)) = (PrivateKey::read_openssh_file(private), PublicKey::read_openssh_file(public)) {
+        if private.public_key().key_data() != public.key_data() {
+            findings.push(Finding::MismatchedPublicKey(name.to_string()));
+        }
+    }
+
+    findings
+}
+
+/// Finds keys named like profile keys (`id_<name>`) in `~/.ssh` that aren't `used` by any profile
+fn orphaned_keys(used: &HashSet<PathBuf>) -> Vec<Finding> {
+    let Ok(entries) = fs::read_dir(ssh::key::ssh_dir()) else {
+        return vec![];
+    };
+    let mut orphaned = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            let Some(name) = file_name.strip_prefix("id_") else {
+                return false;
+            };
+            let name = name.strip_suffix(".pub").unwrap_or(name);
+            // certificates belong to the key they're issued for
+            let name = name.strip_suffix(ssh::key::CERTIFICATE_SUFFIX).unwrap_or(name);
+            // keys kept after rotation don't belong to any profile on purpose
+            if name.ends_with(ssh::key::RETIRED_SUFFIX) {
+                return false;
+            }
+            !used.contains(&path.with_file_name(format!("id_{name}"))) && !DEFAULT_KEYS.contains(&name)
+        })
+        .map(Finding::OrphanedKey)
+        .collect::<Vec<_>>();
+    orphaned.sort_by_key(|finding| finding.to_string());
+
+    orphaned
+}
+
+fn missing_command_keys() -> Result<Vec<Finding>> {
+    let findings = git::ssh_commands()?
+        .into_iter()
+        .filter_map(|(config, command)| {
+            let key = git::identity_file(&command)?;
+            (!Path::new(&key).exists()).then_some(Finding::SshCommandMissingKey { config, key })
+        })
+        .collect();
+
+    Ok(findings)
+}
+
+/// Returns mode of the private key if it can be accessed by anyone but its owner
+#[cfg(unix)]
+fn private_permissions(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+fn private_permissions(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use rand::thread_rng;
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::prelude::{BooleanAssertions, VecAssertions};
+    use ssh_key::{Algorithm, LineEnding};
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    fn add_profile(name: &str) -> Profile {
+        let profile = Profile::new(name, name, &format!("{name}@email.com")).unwrap();
+        profile.clone().save(false).unwrap();
+
+        profile
+    }
+
+    fn write_keys(profile: &Profile) -> PrivateKey {
+        let (private, public) = ssh::key::pair(&profile.email, &ssh::key::r#type::KeyType::Ed25519, &Default::default()).unwrap();
+        ssh::key::write_private_file(Path::new(&profile.private_key()), &private).unwrap();
+        ssh::key::write_public(&profile.name, &public).unwrap();
+
+        private
+    }
+
+    #[rstest]
+    fn healthy(_fake_home: TempDir) {
+        write_keys(&add_profile("test"));
+        // default keys of the user don't belong to profiles
+        let private = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+        private.write_openssh_file(Path::new(&ssh::key::path_private("ed25519")), LineEnding::LF).unwrap();
+
+        assert_that!(check().unwrap().iter().filter(|f| !matches!(f, Finding::SshCommandMissingKey { .. })).count())
+            .is_equal_to(0);
+    }
+
+    #[rstest]
+    fn key_in_place(fake_home: TempDir) {
+        // key named like a profile key, but referenced by a profile with another name
+        let work = Profile::new("work", "Work", "work@email.com").unwrap();
+        write_keys(&work);
+        let mut profile = Profile::new("job", "Job", "job@email.com").unwrap();
+        profile.key = Some(PathBuf::from(work.private_key()));
+        profile.save(false).unwrap();
+        let mut missing = Profile::new("missing", "Missing", "missing@email.com").unwrap();
+        missing.key = Some(fake_home.path().join("keys/missing"));
+        missing.save(false).unwrap();
+
+        let findings = check().unwrap();
+
+        assert_that!(findings.iter().any(|f| matches!(f, Finding::OrphanedKey(_)))).is_false();
+        assert_that!(findings.contains(&Finding::MissingKeys("missing".to_string()))).is_true();
+    }
+
+    #[rstest]
+    fn missing_keys(_fake_home: TempDir) {
+        add_profile("test");
+
+        let findings = check().unwrap();
+
+        assert_that!(findings.contains(&Finding::MissingKeys("test".to_string()))).is_true();
+    }
+
+    #[rstest]
+    fn orphaned_key(_fake_home: TempDir) {
+        write_keys(&Profile::new("removed", "Removed", "removed@email.com").unwrap());
+
+        let findings = check().unwrap();
+
+        assert_that!(findings.contains(&Finding::OrphanedKey(ssh::key::path_private("removed").into()))).is_true();
+        assert_that!(findings.contains(&Finding::OrphanedKey(ssh::key::path_public("removed").into()))).is_true();
+        assert_that!(findings.iter().all(|f| !f.is_fixable())).is_true();
+    }
+
+    #[rstest]
+    fn certificate_not_orphaned(_fake_home: TempDir) {
+        let profile = add_profile("test");
+        write_keys(&profile);
+        fs::write(ssh::key::path_certificate_of(&profile.private_key()), "").unwrap();
+
+        let findings = check().unwrap();
+
+        assert_that!(findings.iter().any(|f| matches!(f, Finding::OrphanedKey(_)))).is_false();
+    }
+
+    #[rstest]
+    #[case::missing(|name: &str| fs::remove_file(ssh::key::path_public(name)).unwrap(), Finding::MissingPublicKey)]
+    #[case::mismatched(|name: &str| {
+        let other = PublicKey::from(&PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap());
+        ssh::key::write_public(name, &other).unwrap();
+    }, Finding::MismatchedPublicKey)]
+    fn public_key(_fake_home: TempDir, #[case] break_key: fn(&str), #[case] expected: fn(String) -> Finding) {
+        let private = write_keys(&add_profile("test"));
+        break_key("test");
+        let finding = expected("test".to_string());
+        assert_that!(check().unwrap().contains(&finding)).is_true();
+
+        fix(&finding).unwrap();
+
+        let public = PublicKey::read_openssh_file(Path::new(&ssh::key::path_public("test"))).unwrap();
+        assert_that!(public.key_data()).is_equal_to(private.public_key().key_data());
+        assert_that!(check().unwrap().contains(&finding)).is_false();
+    }
+
+    #[rstest]
+    #[cfg(unix)]
+    fn bad_permissions(_fake_home: TempDir) {
+        write_keys(&add_profile("test"));
+        let path = PathBuf::from(ssh::key::path_private("test"));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let finding = Finding::BadPermissions { path: path.clone(), mode: 0o644 };
+        assert_that!(check().unwrap().contains(&finding)).is_true();
+
+        fix(&finding).unwrap();
+
+        assert_that!(fs::metadata(&path).unwrap().permissions().mode() & 0o777).is_equal_to(0o600);
+    }
+
+    #[rstest]
+    fn stale_index(_fake_home: TempDir) {
+        write_keys(&add_profile("test"));
+        fs::remove_file(profile::path("test").unwrap()).unwrap();
+        let findings = check().unwrap()
+            .into_iter()
+            .filter(|f| matches!(f, Finding::Index(_)))
+            .collect::<Vec<_>>();
+        assert_that!(findings).has_length(1);
+
+        fix(&findings[0]).unwrap();
+
+        assert_that!(profile::cache::verify().unwrap()).is_empty();
+    }
+
+    #[rstest]
+    fn ssh_command_missing_key(fake_home: TempDir) {
+        let config = fake_home.path().join(".gitconfig");
+        let key = fake_home.path().join(".ssh/id_missing").to_string_lossy().to_string();
+        fs::write(&config, format!("[core]\n\tsshCommand = ssh -i {key} -F /dev/null\n")).unwrap();
+
+        let findings = check().unwrap();
+
+        assert_that!(findings.contains(&Finding::SshCommandMissingKey { config, key })).is_true();
+    }
+}
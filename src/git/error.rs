@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 
 use thiserror::Error;
 
@@ -6,8 +7,14 @@ use thiserror::Error;
 pub(crate) enum Error {
     #[error("Property {0} is empty")]
     EmptyProperty(String),
+    #[error("No profile is bound to {0}")]
+    NotBound(PathBuf),
+    #[error("Profile '{0}' has no remote url patterns")]
+    NoRemotes(String),
     #[error(transparent)]
     Io(#[from] io::Error),
     #[error(transparent)]
     LibGit2(#[from] git2::Error),
+    #[error(transparent)]
+    Settings(#[from] crate::settings::error::Error),
 }
\ No newline at end of file
@@ -0,0 +1,438 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::Config;
+
+use crate::git::error::Error;
+use crate::git::{config, repository, set_user, Result, Scope};
+use crate::profile::model::Profile;
+use crate::profile::profiles_dir;
+
+const GITDIR_PREFIX: &str = "includeif.gitdir:";
+const REMOTE_PREFIX: &str = "includeif.hasconfig:remote.*.url:";
+
+/// Condition of an `[includeIf "<condition>"]` section
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Condition {
+    /// `gitdir:<dir>/` - repository is located under `dir`
+    GitDir(PathBuf),
+    /// `hasconfig:remote.*.url:<pattern>` - repository has a remote with url matching `pattern`
+    Remote(String),
+}
+
+/// Represents an `[includeIf]` section in global git config managed by g
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Binding {
+    pub condition: Condition,
+    pub profile_name: String,
+}
+
+impl Condition {
+    fn key(&self) -> String {
+        format!("includeIf.{self}.path")
+    }
+
+    fn parse(key: &str) -> Option<Self> {
+        let condition = key.strip_suffix(".path")?;
+        if let Some(dir) = condition.strip_prefix(GITDIR_PREFIX) {
+            return Some(Self::GitDir(PathBuf::from(dir.trim_end_matches('/'))));
+        }
+        condition
+            .strip_prefix(REMOTE_PREFIX)
+            .map(|pattern| Self::Remote(pattern.to_string()))
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::GitDir(dir) => write!(f, "gitdir:{}/", dir.display()),
+            Condition::Remote(pattern) => write!(f, "hasconfig:remote.*.url:{pattern}"),
+        }
+    }
+}
+
+/// Binds `profile` to `dir`, so that every repository under `dir` uses it automatically.
+/// Generates an include file for `profile` and points `[includeIf "gitdir:<dir>/"]` in global git config at it.
+///
+/// Returns the canonicalized `dir` the profile has been bound to.
+///
+/// ```
+/// let profile = Profile::load("work").unwrap();
+/// bind(&profile, Path::new("/home/user/work")).unwrap();
+/// ```
+pub fn bind(profile: &Profile, dir: &Path) -> Result<PathBuf> {
+    let dir = dir.canonicalize()?;
+    write_include(profile)?;
+    let mut config = config(Scope::Global)?;
+    let key = Condition::GitDir(dir.clone()).key();
+    config.set_str(&key, &include_path(&profile.name)).unwrap();
+
+    Ok(dir)
+}
+
+/// Removes binding of `dir` from global git config.
+///
+/// Will return [`Error::NotBound`] if there is no binding for `dir`.
+///
+/// ```
+/// unbind(Path::new("/home/user/work")).unwrap();
+/// ```
+pub fn unbind(dir: &Path) -> Result<()> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut config = config(Scope::Global)?;
+    config.remove(&Condition::GitDir(dir.clone()).key())
+        .map_err(|_| Error::NotBound(dir))?;
+
+    Ok(())
+}
+
+/// Replaces all `[includeIf "hasconfig:remote.*.url:<pattern>"]` sections of `profile`
+/// with ones matching [`Profile::remotes`] and re-generates its include file.
+///
+/// Will return [`Error::NoRemotes`] if `profile` has no remote url patterns.
+pub(super) fn bind_remotes(profile: &Profile) -> Result<()> {
+    if profile.remotes.is_empty() {
+        Err(Error::NoRemotes(profile.name.to_string()))?
+    }
+    write_include(profile)?;
+    unbind_remotes(&profile.name)?;
+    let mut config = config(Scope::Global)?;
+    for pattern in &profile.remotes {
+        let key = Condition::Remote(pattern.to_string()).key();
+        config.set_str(&key, &include_path(&profile.name)).unwrap();
+    }
+
+    Ok(())
+}
+
+/// Removes all bindings of profile with specified name, along with its include file.
+///
+/// Returns bindings that were removed.
+pub fn unbind_profile(profile_name: &str) -> Result<Vec<Binding>> {
+    let bindings = bindings()?
+        .into_iter()
+        .filter(|b| b.profile_name == profile_name)
+        .collect::<Vec<_>>();
+    let mut config = config(Scope::Global)?;
+    for binding in &bindings {
+        config.remove(&binding.condition.key())?;
+    }
+    let include_path = include_path(profile_name);
+    if Path::new(&include_path).exists() {
+        fs::remove_file(include_path)?;
+    }
+
+    Ok(bindings)
+}
+
+/// Moves all bindings of profile with name `old_name` to `profile`, e.g. after the profile got renamed.
+/// Include file of the old profile is replaced by the one of `profile`.
+///
+/// ```
+/// let profile = Profile::load("new").unwrap();
+/// rename("old", &profile).unwrap();
+/// ```
+pub fn rename(old_name: &str, profile: &Profile) -> Result<()> {
+    let bindings = unbind_profile(old_name)?;
+    if bindings.is_empty() {
+        return Ok(());
+    }
+    write_include(profile)?;
+    let mut config = config(Scope::Global)?;
+    for binding in bindings {
+        config.set_str(&binding.condition.key(), &include_path(&profile.name)).unwrap();
+    }
+
+    Ok(())
+}
+
+/// Re-generates include file of `profile` and its remote bindings if it's bound to anything.
+/// Should be called whenever `profile` changes, otherwise bindings would keep the old identity.
+pub fn refresh(profile: &Profile) -> Result<()> {
+    if !Path::new(&include_path(&profile.name)).exists() {
+        return Ok(());
+    }
+    let has_remote_bindings = bindings()?
+        .iter()
+        .any(|b| b.profile_name == profile.name && matches!(b.condition, Condition::Remote(_)));
+    if has_remote_bindings && !profile.remotes.is_empty() {
+        return bind_remotes(profile);
+    }
+    unbind_remotes(&profile.name)?;
+
+    write_include(profile)
+}
+
+/// Lists all bindings found in global git config, in the order they appear there.
+/// Only bindings pointing at include files generated by g are taken into account.
+pub fn bindings() -> Result<Vec<Binding>> {
+    let config = config(Scope::Global)?;
+    let includes_dir = includes_dir();
+    let mut bindings = Vec::new();
+    let mut entries = config.entries(Some(r"includeif\..*\.path"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let Some(profile_name) = value.strip_prefix(&format!("{includes_dir}/")) else {
+            continue;
+        };
+        if let Some(condition) = Condition::parse(name) {
+            bindings.push(Binding { condition, profile_name: profile_name.to_string() });
+        }
+    }
+
+    Ok(bindings)
+}
+
+/// Finds the binding that applies to the repository in current working directory, if any.
+/// When multiple bindings match, the last one wins - same as in git.
+pub fn matching() -> Result<Option<Binding>> {
+    let Some(repo) = repository() else {
+        return Ok(None);
+    };
+    let git_dir = repo.path().canonicalize()?;
+    let remote_urls = repo.remotes()?
+        .iter()
+        .flatten()
+        .filter_map(|name| repo.find_remote(name).ok())
+        .filter_map(|remote| remote.url().map(String::from))
+        .collect::<Vec<_>>();
+    let binding = bindings()?
+        .into_iter()
+        .rev()
+        .find(|b| match &b.condition {
+            Condition::GitDir(dir) => git_dir.starts_with(dir),
+            Condition::Remote(pattern) => remote_urls.iter().any(|url| glob_match(pattern, url)),
+        });
+
+    Ok(binding)
+}
+
+fn unbind_remotes(profile_name: &str) -> Result<()> {
+    let mut config = config(Scope::Global)?;
+    for binding in bindings()? {
+        if binding.profile_name == profile_name && matches!(binding.condition, Condition::Remote(_)) {
+            config.remove(&binding.condition.key())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Matches `text` against a glob `pattern` the way `hasconfig:remote.*.url` does:
+/// `*` matches anything except `/`, `**` matches anything, `?` matches a single character except `/`.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            ['*', '*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            ['*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != '/')
+                .any(|i| matches(rest, &text[i..])),
+            ['?', rest @ ..] => matches!(text, [c, ..] if *c != '/') && matches(rest, &text[1..]),
+            [p, rest @ ..] => matches!(text, [c, ..] if c == p) && matches(rest, &text[1..]),
+        }
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    matches(&pattern, &text)
+}
+
+fn write_include(profile: &Profile) -> Result<()> {
+    fs::create_dir_all(includes_dir())?;
+    let mut config = Config::open(Path::new(&include_path(&profile.name)))?;
+    set_user(&mut config, profile)?;
+
+    Ok(())
+}
+
+pub(super) fn include_path(profile_name: &str) -> String {
+    format!("{}/{profile_name}", includes_dir())
+}
+
+fn includes_dir() -> String {
+    format!("{}/.includes", profiles_dir())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::iter::ContainingIntoIterAssertions;
+    use spectral::prelude::{BooleanAssertions, OptionAssertions, PathAssertions, VecAssertions};
+    use tempfile::{tempdir, TempDir};
+
+    use git2::Repository;
+
+    use super::*;
+    use crate::git::{ssh_command, DEFAULT_SSH_COMMAND};
+
+    #[fixture]
+    fn profile() -> Profile {
+        let mut profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+        profile.remotes = vec!["git@github.com:acme/**".to_string()];
+
+        profile
+    }
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    fn bind_writes_include_and_binding(profile: Profile, fake_home: TempDir) {
+        let work = fake_home.path().join("work");
+        fs::create_dir(&work).unwrap();
+
+        let dir = bind(&profile, &work).unwrap();
+
+        let include = Config::open(Path::new(&include_path(&profile.name))).unwrap()
+            .snapshot().unwrap();
+        assert_that!(include.get_str("user.name").unwrap())
+            .is_equal_to(&*profile.username);
+        assert_that!(include.get_str("user.email").unwrap())
+            .is_equal_to(&*profile.email);
+        assert_that!(include.get_str("core.sshCommand").unwrap())
+            .is_equal_to(&*ssh_command(DEFAULT_SSH_COMMAND, &profile));
+        assert_that!(bindings().unwrap())
+            .contains(Binding { condition: Condition::GitDir(dir), profile_name: profile.name.to_string() });
+    }
+
+    #[rstest]
+    fn unbind_removes_binding(profile: Profile, fake_home: TempDir) {
+        let work = fake_home.path().join("work");
+        fs::create_dir(&work).unwrap();
+        bind(&profile, &work).unwrap();
+
+        unbind(&work).unwrap();
+
+        assert_that!(bindings().unwrap()).is_empty();
+    }
+
+    #[rstest]
+    fn unbind_not_bound(fake_home: TempDir) {
+        let err = unbind(fake_home.path()).unwrap_err();
+
+        assert_that!(matches!(err, Error::NotBound(_))).is_true();
+    }
+
+    #[rstest]
+    fn rename_moves_bindings(profile: Profile, fake_home: TempDir) {
+        let work = fake_home.path().join("work");
+        fs::create_dir(&work).unwrap();
+        let dir = bind(&profile, &work).unwrap();
+        bind_remotes(&profile).unwrap();
+        let mut renamed = profile.clone();
+        renamed.name = "renamed".to_string();
+
+        rename(&profile.name, &renamed).unwrap();
+
+        let mut bound = bindings().unwrap();
+        bound.sort_by_key(|b| b.condition.to_string());
+        assert_that!(bound).is_equal_to(vec![
+            Binding { condition: Condition::GitDir(dir), profile_name: renamed.name.to_string() },
+            Binding { condition: Condition::Remote(profile.remotes[0].to_string()), profile_name: renamed.name.to_string() },
+        ]);
+        assert_that!(Path::new(&include_path(&profile.name))).does_not_exist();
+        assert_that!(Path::new(&include_path(&renamed.name))).exists();
+    }
+
+    #[rstest]
+    fn bind_remotes_replaces_previous(mut profile: Profile, _fake_home: TempDir) {
+        bind_remotes(&profile).unwrap();
+        profile.remotes = vec!["git@github.com:corp/**".to_string()];
+
+        bind_remotes(&profile).unwrap();
+
+        let expected = Binding {
+            condition: Condition::Remote("git@github.com:corp/**".to_string()),
+            profile_name: profile.name.to_string(),
+        };
+        assert_that!(bindings().unwrap()).is_equal_to(vec![expected]);
+    }
+
+    #[rstest]
+    fn bind_remotes_without_remotes(mut profile: Profile, _fake_home: TempDir) {
+        profile.remotes.clear();
+
+        let err = bind_remotes(&profile).unwrap_err();
+
+        assert_that!(matches!(err, Error::NoRemotes(_))).is_true();
+    }
+
+    #[rstest]
+    fn refresh_without_remotes_unbinds_them(mut profile: Profile, _fake_home: TempDir) {
+        bind_remotes(&profile).unwrap();
+        profile.remotes.clear();
+
+        refresh(&profile).unwrap();
+
+        assert_that!(bindings().unwrap()).is_empty();
+        assert_that!(PathBuf::from(include_path(&profile.name))).exists();
+    }
+
+    #[rstest]
+    fn unbind_profile_removes_everything(profile: Profile, fake_home: TempDir) {
+        let work = fake_home.path().join("work");
+        fs::create_dir(&work).unwrap();
+        bind(&profile, &work).unwrap();
+        bind_remotes(&profile).unwrap();
+
+        let unbound = unbind_profile(&profile.name).unwrap();
+
+        assert_that!(unbound).has_length(2);
+        assert_that!(bindings().unwrap()).is_empty();
+        assert_that!(PathBuf::from(include_path(&profile.name))).does_not_exist();
+    }
+
+    #[rstest]
+    fn matching_gitdir_in_nested_repo(profile: Profile, fake_home: TempDir) {
+        let work = fake_home.path().join("work");
+        let repo = work.join("project");
+        fs::create_dir_all(&repo).unwrap();
+        Repository::init(&repo).unwrap();
+        bind(&profile, &work).unwrap();
+        env::set_current_dir(&repo).unwrap();
+
+        let binding = matching().unwrap();
+
+        assert_that!(binding.map(|b| b.profile_name)).is_some().is_equal_to(profile.name);
+    }
+
+    #[rstest]
+    fn matching_remote(profile: Profile, fake_home: TempDir) {
+        let repo_path = fake_home.path().join("project");
+        let repo = Repository::init(&repo_path).unwrap();
+        repo.remote("origin", "git@github.com:acme/project.git").unwrap();
+        bind_remotes(&profile).unwrap();
+        env::set_current_dir(&repo_path).unwrap();
+
+        let binding = matching().unwrap();
+
+        assert_that!(binding.map(|b| b.profile_name)).is_some().is_equal_to(profile.name);
+    }
+
+    #[rstest]
+    #[case::exact("git@github.com:acme/g.git", "git@github.com:acme/g.git", true)]
+    #[case::star("git@github.com:acme/*", "git@github.com:acme/g.git", true)]
+    #[case::star_no_slash("git@github.com:*", "git@github.com:acme/g.git", false)]
+    #[case::double_star("git@github.com:acme/**", "git@github.com:acme/sub/g.git", true)]
+    #[case::question("git@github.com:acme/?.git", "git@github.com:acme/g.git", true)]
+    #[case::other_org("git@github.com:acme/**", "git@github.com:corp/g.git", false)]
+    fn glob(#[case] pattern: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_that!(glob_match(pattern, text)).is_equal_to(expected);
+    }
+}
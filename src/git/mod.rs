@@ -1,53 +1,155 @@
-use std::{env, io};
+use std::{env, fs, io};
+use std::collections::BTreeMap;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use git2::Config;
+use git2::{Config, ConfigLevel, Repository};
 
 use crate::git::error::Error;
-use crate::home;
-use crate::profile::model::Profile;
+use crate::{home, ssh};
+use crate::profile::model::{known_hosts_path, Profile};
+use crate::profile::signers;
+use crate::settings::Settings;
 
 type Result<T> = std::result::Result<T, error::Error>;
 pub mod error;
+pub mod include;
 
-/// Configures `profile` for git: `user.name`, `user.email` and `core.sshCommand`.
-/// Local git config is used if current working directory is a git repository and `global` is set to `false`.
-/// Otherwise, global config is used.
+const INSTEAD_OF_PATTERN: &str = r"^url\..*\.insteadof$";
+
+/// `core.sshCommand` template used unless [`Settings::ssh_command`] is set.
+/// Offers only profile's key, while the rest of user's ssh config (ProxyJump, ports, ...) still applies.
+pub(crate) const DEFAULT_SSH_COMMAND: &str = "ssh -i {key} -o IdentitiesOnly=yes";
+
+/// Git config file to read from or write to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// `config` of the repository current working directory belongs to
+    Local,
+    /// `config.worktree` of the worktree current working directory belongs to
+    Worktree,
+    /// `$GIT_CONFIG_GLOBAL`, `~/.gitconfig` or `$XDG_CONFIG_HOME/git/config` - whichever git would use
+    Global,
+    /// `$XDG_CONFIG_HOME/git/config`
+    Xdg,
+    /// `$GIT_CONFIG_SYSTEM` or `/etc/gitconfig`
+    System,
+}
+
+impl Scope {
+    fn is_repository(&self) -> bool {
+        matches!(self, Scope::Local | Scope::Worktree)
+    }
+}
+
+/// Determines how [`configure_user`] applies a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Write the keys directly into config of chosen scope
+    Keys(Scope),
+    /// Manage `[includeIf "hasconfig:remote.*.url:<pattern>"]` sections in global config
+    /// for each of profile's remote url patterns
+    Includes,
+}
+
+/// Configures `profile` for git: `user.name`, `user.email`, `core.sshCommand` and, if [`Profile::sign`] is set,
+/// signing - `gpg.format`, `user.signingkey` (openpgp key if [`Profile::gpg_key`] is set, ssh key otherwise),
+/// `gpg.ssh.allowedSignersFile`, `commit.gpgsign` and `tag.gpgsign`.
+/// A profile that doesn't sign leaves signing config alone, unless g set it up for another profile -
+/// then `commit.gpgsign` and `tag.gpgsign` are set to `false`.
+///
+/// With [`Mode::Keys`], config of the chosen [`Scope`] is used.
+/// Repository scopes fall back to global config if current working directory isn't inside a git repository.
+/// [`Scope::Worktree`] enables `extensions.worktreeConfig` in repository config if it's not enabled yet.
+///
+/// With [`Mode::Includes`], the keys are written into profile's include file instead,
+/// which global config includes for repositories whose remotes match [`Profile::remotes`].
 ///
 /// ```
 /// let profile = Profile::new("example", "Example profile", "user@example.com");
-/// configure_user(&profile, false);
+/// configure_user(&profile, Mode::Keys(Scope::Local));
 /// ```
-pub fn configure_user(profile: &Profile, global: bool) -> Result<()> {
-    let is_inside_repo = is_inside_repo();
-    if !is_inside_repo && !global {
+pub fn configure_user(profile: &Profile, mode: Mode) -> Result<()> {
+    let scope = match mode {
+        Mode::Keys(scope) => scope,
+        Mode::Includes => return include::bind_remotes(profile),
+    };
+    if scope.is_repository() && !is_inside_repo() {
         println!("No git repository detected, setting profile in global config");
     };
-    let global = global || !is_inside_repo;
-    let mut config = config(global)?;
-    // Can safely unwrap those because they throw only for invalid git config key
-    config.set_str("user.name", &profile.username).unwrap();
-    config.set_str("user.email", &profile.email).unwrap();
-    config.set_str("core.sshCommand", &ssh_command(&profile.name)).unwrap();
+    let scope = effective_scope(scope);
+    if scope == Scope::Worktree {
+        enable_worktree_config()?;
+    }
+    let mut config = config(scope)?;
+    set_user(&mut config, profile)?;
+
+    Ok(())
+}
+
+/// Makes ssh remotes on `hosts` use `profile`'s host aliases from `~/.ssh/config.d/g`
+/// by setting `url."git@<host>-<profile>:".insteadOf` (and its `ssh://` form) in config of chosen [`Scope`],
+/// replacing insteadOf rewrites of the same remotes to other aliases.
+///
+/// `core.sshCommand` is removed from the config, since ssh picks the key by host alias.
+/// Repository scopes fall back to global config the same way [`configure_user`] does.
+///
+/// ```
+/// let profile = Profile::new("work", "Work", "user@example.com");
+/// alias_remotes(&profile, Scope::Local, &["github.com".to_string()]);
+/// ```
+pub fn alias_remotes(profile: &Profile, scope: Scope, hosts: &[String]) -> Result<()> {
+    let scope = effective_scope(scope);
+    let mut config = config(scope)?;
+    let rewrites = hosts
+        .iter()
+        .flat_map(|host| {
+            let alias = ssh::config::alias(host, &profile.name);
+            [
+                (format!("git@{alias}:"), format!("git@{host}:")),
+                (format!("ssh://git@{alias}/"), format!("ssh://git@{host}/")),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let is_replaced = |value: &str| rewrites.iter().any(|(_, original)| original == value);
+    let mut values = BTreeMap::<String, Vec<String>>::new();
+    let mut entries = config.entries(Some(INSTEAD_OF_PATTERN))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+            values.entry(name.to_string()).or_default().push(value.to_string());
+        }
+    }
+    drop(entries);
+    for (name, values) in values.iter().filter(|(_, values)| values.iter().any(|value| is_replaced(value))) {
+        // other values of the same key rewrite different remotes, so they're added back
+        config.remove_multivar(name, ".*")?;
+        for value in values.iter().filter(|value| !is_replaced(value)) {
+            // "^$" matches only an empty value, so the value is added next to the ones added back before
+            config.set_multivar(name, "^$", value)?;
+        }
+    }
+    for (alias, original) in rewrites {
+        config.set_str(&format!("url.{alias}.insteadOf"), &original)?;
+    }
+    // not set in this config is fine, ssh config chooses the key now
+    let _ = config.remove("core.sshCommand");
 
     Ok(())
 }
 
-/// Gets `user.name` and `user.email` from git config.
-/// Local git config is used if current working directory is a git repository and `global` is set to `false`.
-/// Otherwise, global config is used.
+/// Gets `user.name` and `user.email` from git config of chosen [`Scope`].
+/// Repository scopes fall back to global config if current working directory isn't inside a git repository.
 ///
 /// Will return [`Error::EmptyProperty`] if either `user.name` or `user.email` is not set.
 /// Other errors are forwarded from [`git2`].
 ///
 /// ```
-/// let (username, email) = get_username_and_email(true).expect("Can't open ~/.gitconfig");
+/// let (username, email) = get_username_and_email(Scope::Global).expect("Can't open ~/.gitconfig");
 /// ```
-pub fn get_username_and_email(global: bool) -> Result<(String, String)> {
-    let global = global || !is_inside_repo();
-    let config = config(global)?;
+pub fn get_username_and_email(scope: Scope) -> Result<(String, String)> {
+    let config = config(effective_scope(scope))?;
     let username = config.get_string("user.name")
         .map_err(|_| Error::EmptyProperty("user.name".to_string()))?;
     let email = config.get_string("user.email")
@@ -56,35 +158,373 @@ pub fn get_username_and_email(global: bool) -> Result<(String, String)> {
     Ok((username, email))
 }
 
+/// Gets `user.name` and `user.email` the way git resolves them - from all config files merged together,
+/// where worktree config takes precedence over repository config, which takes precedence over global, xdg and system.
+///
+/// If a binding matches current repository, its identity takes precedence over everything but repository scopes,
+/// same as with `[includeIf]` in git.
+///
+/// Will return [`Error::EmptyProperty`] if either `user.name` or `user.email` is not set in any of them.
+pub fn get_effective_username_and_email() -> Result<(String, String)> {
+    let mut config = merged_config()?;
+    let set_in_repository = config.get_entry("user.name")
+        .map(|entry| matches!(entry.level(), ConfigLevel::Local | ConfigLevel::Worktree))
+        .unwrap_or(false);
+    if !set_in_repository {
+        if let Some(binding) = include::matching()? {
+            config = Config::open(Path::new(&include::include_path(&binding.profile_name)))?;
+        }
+    }
+    let username = config.get_string("user.name")
+        .map_err(|_| Error::EmptyProperty("user.name".to_string()))?;
+    let email = config.get_string("user.email")
+        .map_err(|_| Error::EmptyProperty("user.email".to_string()))?;
+
+    Ok((username, email))
+}
+
+/// Reads `core.sshCommand` from every existing git config that sets it, together with the config's path.
+/// Repository configs are read only if current working directory is inside a repository.
+///
+/// ```
+/// for (config, command) in ssh_commands().expect("Can't read git configs") {
+///     println!("{}: {command}", config.display());
+/// }
+/// ```
+pub fn ssh_commands() -> Result<Vec<(PathBuf, String)>> {
+    let commands = existing_configs()?
+        .into_iter()
+        .filter_map(|(_, path)| {
+            let command = Config::open(&path).ok()?.get_string("core.sshCommand").ok()?;
+            Some((path, command))
+        })
+        .collect();
+
+    Ok(commands)
+}
+
+/// Points `core.sshCommand` and `user.signingkey` using ssh keys and known_hosts of profile `old_name`
+/// at the ones of `profile` in every existing git config, e.g. after the profile got renamed from `old_name`.
+/// Keys referenced in place ([`Profile::key`]) keep their paths.
+/// Repository configs are updated only if current working directory is inside a repository.
+///
+/// Returns paths of configs that changed.
+///
+/// ```
+/// let profile = Profile::load("new").unwrap();
+/// for config in rename_keys("old", &profile).expect("Can't update git configs") {
+///     println!("updated: {}", config.display());
+/// }
+/// ```
+pub fn rename_keys(old_name: &str, profile: &Profile) -> Result<Vec<PathBuf>> {
+    let (old_private, old_public) = match profile.key {
+        Some(_) => (profile.private_key(), profile.public_key()),
+        None => (ssh::key::path_private(old_name), ssh::key::path_public(old_name)),
+    };
+    let renamed = [
+        (old_private.clone(), profile.private_key()),
+        (known_hosts_path(old_name), known_hosts_path(&profile.name)),
+    ];
+    let mut changed = vec![];
+    for (scope, path) in existing_configs()? {
+        let mut config = config(scope)?;
+        let mut updated = false;
+        if let Ok(command) = config.get_string("core.sshCommand") {
+            if identity_file(&command).is_some_and(|key| key == old_private) {
+                let renamed_command = renamed
+                    .iter()
+                    .fold(command.clone(), |command, (old, new)| replace_path(&command, old, new));
+                if renamed_command != command {
+                    config.set_str("core.sshCommand", &renamed_command)?;
+                    updated = true;
+                }
+            }
+        }
+        if old_public != profile.public_key() && config.get_string("user.signingkey").is_ok_and(|key| key == old_public) {
+            config.set_str("user.signingkey", &profile.public_key())?;
+            updated = true;
+        }
+        if updated {
+            changed.push(path);
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Replaces path `old` in ssh `command` with `new`, shell quoted the way [`ssh_command`] writes them.
+/// Commands with unquoted paths are written by older versions of g, their paths are replaced as they are.
+fn replace_path(command: &str, old: &str, new: &str) -> String {
+    let quoted = shell_quote(old);
+    if command.contains(&quoted) {
+        command.replace(&quoted, &shell_quote(new))
+    } else {
+        command.replace(old, new)
+    }
+}
+
+/// Extracts identity file passed with `-i` or `-o IdentityFile=` from ssh command, with shell quoting removed
+pub(crate) fn identity_file(command: &str) -> Option<String> {
+    let words = shell_words(command);
+    let mut args = words.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        if arg == "-i" {
+            return args.next().map(String::from);
+        }
+        if let Some(path) = arg.strip_prefix("-i") {
+            return Some(path.to_string());
+        }
+        let option = match arg {
+            "-o" => args.next(),
+            _ => arg.strip_prefix("-o"),
+        };
+        if let Some((name, path)) = option.and_then(|option| option.split_once('=')) {
+            if name.eq_ignore_ascii_case("IdentityFile") {
+                return Some(path.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// Splits `command` into words the way a POSIX shell does, removing quotes without expanding anything
+fn shell_words(command: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => word.get_or_insert_with(String::new).extend(chars.by_ref().take_while(|&c| c != '\'')),
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    words
+}
+
+/// Quotes `value` for the POSIX shell git runs `core.sshCommand` with
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Lists scopes whose config files exist, skipping the ones that resolve to the same file.
+/// Repository scopes are listed only if current working directory is inside a repository.
+fn existing_configs() -> Result<Vec<(Scope, PathBuf)>> {
+    let mut configs: Vec<(Scope, PathBuf)> = vec![];
+    for scope in [Scope::System, Scope::Xdg, Scope::Global, Scope::Local, Scope::Worktree] {
+        if scope.is_repository() && !is_inside_repo() {
+            continue;
+        }
+        let path = config_path(scope)?;
+        if path.exists() && configs.iter().all(|(_, p)| p != &path) {
+            configs.push((scope, path));
+        }
+    }
+
+    Ok(configs)
+}
+
+/// Discovers the repository current working directory belongs to, the same way git does:
+/// walking up from current working directory, following `.git` files of worktrees and submodules
+/// and respecting `$GIT_DIR`, `$GIT_WORK_TREE` and `$GIT_CEILING_DIRECTORIES`.
+fn repository() -> Option<Repository> {
+    Repository::open_from_env().ok()
+}
+
 fn is_inside_repo() -> bool {
-    let current_dir = env::current_dir().unwrap();
-    let path_str = format!("{}/.git", current_dir.to_str().unwrap());
-    let path = Path::new(&path_str);
+    repository().is_some()
+}
+
+fn effective_scope(scope: Scope) -> Scope {
+    if scope.is_repository() && !is_inside_repo() { Scope::Global } else { scope }
+}
+
+/// Per-worktree config is ignored by git unless `extensions.worktreeConfig` is enabled in repository config
+fn enable_worktree_config() -> Result<()> {
+    let mut config = config(Scope::Local)?;
+    if !config.get_bool("extensions.worktreeConfig").unwrap_or(false) {
+        config.set_bool("extensions.worktreeConfig", true)?;
+    }
+
+    Ok(())
+}
+
+fn current_repository() -> Result<Repository> {
+    let repo = repository().ok_or_else(|| git2::Error::from_str("Not inside a git repository"))?;
 
-    path.exists() && path.is_dir()
+    Ok(repo)
+}
+
+/// Linked worktrees share config with the repository they belong to,
+/// their git dir points at it with a `commondir` file.
+fn common_dir(repo: &Repository) -> PathBuf {
+    let git_dir = repo.path();
+    fs::read_to_string(git_dir.join("commondir"))
+        .map(|common_dir| git_dir.join(common_dir.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf())
 }
 
 const TIMEOUT: Duration = Duration::from_millis(1);
-fn config(global: bool) -> Result<Config> {
-    let config_path = if global {
-        format!("{}/.gitconfig", home())
-    } else {
-        format!("{}/.git/config", env::current_dir()?.display())
-    };
-    let lock_path = PathBuf::from(format!("{config_path}.lock"));
+fn config(scope: Scope) -> Result<Config> {
+    let config_path = config_path(scope)?;
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let lock_path = PathBuf::from(format!("{}.lock", config_path.display()));
     let start = Instant::now();
     while lock_path.exists() {
         if start.elapsed() >= TIMEOUT {
             Err(io::Error::new(ErrorKind::TimedOut, &*format!("Timed out waiting for {}", lock_path.display())))?;
         }
     }
-    let config = Config::open(Path::new(&config_path))?;
+    let config = Config::open(&config_path)?;
+
+    Ok(config)
+}
+
+fn config_path(scope: Scope) -> Result<PathBuf> {
+    let path = match scope {
+        Scope::Local => common_dir(&current_repository()?).join("config"),
+        Scope::Worktree => current_repository()?.path().join("config.worktree"),
+        Scope::Global => global_config_path(
+            Path::new(&home()),
+            env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            env::var_os("GIT_CONFIG_GLOBAL").map(PathBuf::from),
+        ),
+        Scope::Xdg => xdg_config_path(Path::new(&home()), env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)),
+        Scope::System => env::var_os("GIT_CONFIG_SYSTEM")
+            .map(PathBuf::from)
+            .or_else(|| Config::find_system().ok())
+            .unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+    };
+
+    Ok(path)
+}
+
+/// Resolves global config the same way git does: `$GIT_CONFIG_GLOBAL` if set, otherwise `~/.gitconfig`,
+/// unless it doesn't exist while xdg config does - then the xdg one is used.
+fn global_config_path(home: &Path, xdg_config_home: Option<PathBuf>, config_global: Option<PathBuf>) -> PathBuf {
+    if let Some(config_global) = config_global {
+        return config_global;
+    }
+    let home_config = home.join(".gitconfig");
+    let xdg_config = xdg_config_path(home, xdg_config_home);
+    if !home_config.exists() && xdg_config.exists() {
+        return xdg_config;
+    }
+
+    home_config
+}
+
+/// Lists user config files git reads, in order of precedence: xdg config and `~/.gitconfig`,
+/// or only `$GIT_CONFIG_GLOBAL` if set - git skips xdg config then.
+fn user_config_paths(
+    home: &Path, xdg_config_home: Option<PathBuf>, config_global: Option<PathBuf>,
+) -> Vec<(PathBuf, ConfigLevel)> {
+    match config_global {
+        Some(config_global) => vec![(config_global, ConfigLevel::Global)],
+        None => vec![
+            (xdg_config_path(home, xdg_config_home), ConfigLevel::XDG),
+            (home.join(".gitconfig"), ConfigLevel::Global),
+        ],
+    }
+}
+
+fn xdg_config_path(home: &Path, xdg_config_home: Option<PathBuf>) -> PathBuf {
+    xdg_config_home
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| home.join(".config"))
+        .join("git/config")
+}
+
+/// Merges all config files git would read, in order of precedence
+fn merged_config() -> Result<Config> {
+    let mut files = vec![];
+    if env::var_os("GIT_CONFIG_NOSYSTEM").is_none() {
+        files.push((config_path(Scope::System)?, ConfigLevel::System));
+    }
+    files.extend(user_config_paths(
+        Path::new(&home()),
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+        env::var_os("GIT_CONFIG_GLOBAL").map(PathBuf::from),
+    ));
+    if is_inside_repo() {
+        let local = config_path(Scope::Local)?;
+        let worktree_config_enabled = Config::open(&local)?
+            .get_bool("extensions.worktreeConfig")
+            .unwrap_or(false);
+        files.push((local, ConfigLevel::Local));
+        if worktree_config_enabled {
+            files.push((config_path(Scope::Worktree)?, ConfigLevel::Worktree));
+        }
+    }
+    let mut config = Config::new()?;
+    for (path, level) in files.into_iter().filter(|(path, _)| path.exists()) {
+        config.add_file(&path, level, false)?;
+    }
 
     Ok(config)
 }
 
-fn ssh_command(profile_name: &str) -> String {
-    format!("ssh -i {}/.ssh/id_{profile_name} -F /dev/null", home())
+fn set_user(config: &mut Config, profile: &Profile) -> Result<()> {
+    let template = Settings::load()?.ssh_command;
+    // Can safely unwrap those because they throw only for invalid git config key
+    config.set_str("user.name", &profile.username).unwrap();
+    config.set_str("user.email", &profile.email).unwrap();
+    let command = ssh_command(template.as_deref().unwrap_or(DEFAULT_SSH_COMMAND), profile);
+    config.set_str("core.sshCommand", &command).unwrap();
+    // Signing set up by g for another profile is turned off explicitly, so that its key isn't used anymore,
+    // while user's own signing setup in config of a non-signing profile is left alone
+    let set_by_g = config.get_string("gpg.ssh.allowedSignersFile").is_ok_and(|file| file == signers::path());
+    if profile.sign || set_by_g {
+        match &profile.gpg_key {
+            Some(key) => {
+                config.set_str("gpg.format", "openpgp").unwrap();
+                config.set_str("user.signingkey", key).unwrap();
+            }
+            None => {
+                config.set_str("gpg.format", "ssh").unwrap();
+                config.set_str("user.signingkey", &profile.public_key()).unwrap();
+            }
+        }
+        config.set_str("gpg.ssh.allowedSignersFile", &signers::path()).unwrap();
+        for key in ["commit.gpgsign", "tag.gpgsign"] {
+            config.set_bool(key, profile.sign).unwrap();
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders `core.sshCommand` of `profile` from `template`,
+/// replacing `{key}` with shell quoted path of its private key and `{profile}` with its name.
+/// If the profile has its own known_hosts, its shell quoted path is appended as `UserKnownHostsFile`.
+fn ssh_command(template: &str, profile: &Profile) -> String {
+    let command = template
+        .replace("{key}", &shell_quote(&profile.private_key()))
+        .replace("{profile}", &profile.name);
+    match profile.known_hosts() {
+        Some(known_hosts) => format!("{command} -o UserKnownHostsFile={}", shell_quote(&known_hosts)),
+        None => command,
+    }
 }
 
 #[cfg(test)]
@@ -94,11 +534,13 @@ mod test {
     use git2::{Config, Repository};
     use rstest::{fixture, rstest};
     use spectral::assert_that;
-    use tempfile::{tempdir, TempDir};
+    use tempfile::{tempdir, Builder, TempDir};
 
     use super::*;
 
     mod configure_user {
+        use spectral::prelude::BooleanAssertions;
+
         use super::*;
 
         #[fixture]
@@ -127,7 +569,7 @@ mod test {
         fn set_local_config_in_repo(profile: &Profile, fake_repo: TempDir) {
             env::set_current_dir(fake_repo.path()).unwrap();
 
-            configure_user(profile, false).unwrap();
+            configure_user(profile, Mode::Keys(Scope::Local)).unwrap();
             let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
                 .snapshot().unwrap();
 
@@ -136,7 +578,146 @@ mod test {
             assert_that!(config.get_str("user.email").unwrap())
                 .is_equal_to(&*profile.email);
             assert_that!(config.get_str("core.sshCommand").unwrap())
-                .is_equal_to(&*ssh_command(&profile.name));
+                .is_equal_to(&*ssh_command(DEFAULT_SSH_COMMAND, profile));
+        }
+
+        #[rstest]
+        fn set_signing(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let config_path = fake_repo.path().join(".git/config");
+            let mut profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            profile.sign = true;
+            Config::open(&config_path).unwrap().set_str("user.signingkey", "/some/other/key.pub").unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&config_path).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("gpg.format").unwrap()).is_equal_to("ssh");
+            assert_that!(config.get_str("user.signingkey").unwrap())
+                .is_equal_to(&*profile.public_key());
+            assert_that!(config.get_str("gpg.ssh.allowedSignersFile").unwrap())
+                .is_equal_to(&*signers::path());
+            assert_that!(config.get_bool("commit.gpgsign").unwrap()).is_true();
+            assert_that!(config.get_bool("tag.gpgsign").unwrap()).is_true();
+        }
+
+        #[rstest]
+        fn keep_own_signing(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let config_path = fake_repo.path().join(".git/config");
+            let profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            let mut config = Config::open(&config_path).unwrap();
+            config.set_str("gpg.format", "openpgp").unwrap();
+            config.set_str("user.signingkey", "3AA5C34371567BD2").unwrap();
+            config.set_bool("commit.gpgsign", true).unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&config_path).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("gpg.format").unwrap()).is_equal_to("openpgp");
+            assert_that!(config.get_str("user.signingkey").unwrap()).is_equal_to("3AA5C34371567BD2");
+            assert_that!(config.get_bool("commit.gpgsign").unwrap()).is_true();
+            assert_that!(config.get_str("gpg.ssh.allowedSignersFile").is_err()).is_true();
+            assert_that!(config.get_bool("tag.gpgsign").is_err()).is_true();
+        }
+
+        #[rstest]
+        fn turn_off_signing_of_other_profile(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let mut signing = Profile::new("signing", "Test Profile", "em@i.l").unwrap();
+            signing.sign = true;
+            let profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            configure_user(&signing, Mode::Keys(Scope::Local)).unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_bool("commit.gpgsign").unwrap()).is_false();
+            assert_that!(config.get_bool("tag.gpgsign").unwrap()).is_false();
+            assert_that!(config.get_str("user.signingkey").unwrap())
+                .is_equal_to(&*profile.public_key());
+        }
+
+        #[rstest]
+        fn set_gpg_signing(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let mut profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            profile.sign = true;
+            profile.gpg_key = Some("3AA5C34371567BD2".to_string());
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("gpg.format").unwrap()).is_equal_to("openpgp");
+            assert_that!(config.get_str("user.signingkey").unwrap()).is_equal_to("3AA5C34371567BD2");
+        }
+
+        #[rstest]
+        fn set_key_in_place(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let mut profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            profile.sign = true;
+            profile.key = Some(PathBuf::from("/mnt/keys/work"));
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("core.sshCommand").unwrap())
+                .is_equal_to("ssh -i '/mnt/keys/work' -o IdentitiesOnly=yes");
+            assert_that!(config.get_str("user.signingkey").unwrap()).is_equal_to("/mnt/keys/work.pub");
+        }
+
+        #[rstest]
+        #[case::default(DEFAULT_SSH_COMMAND, "/mnt/keys/work", "ssh -i '/mnt/keys/work' -o IdentitiesOnly=yes")]
+        #[case::placeholders(
+            "ssh -i {key} -o ProxyJump=bastion -F ~/.ssh/config-{profile}",
+            "/mnt/keys/work",
+            "ssh -i '/mnt/keys/work' -o ProxyJump=bastion -F ~/.ssh/config-test"
+        )]
+        #[case::no_placeholders("ssh", "/mnt/keys/work", "ssh")]
+        #[case::space(DEFAULT_SSH_COMMAND, "/mnt/my keys/work", "ssh -i '/mnt/my keys/work' -o IdentitiesOnly=yes")]
+        #[case::quote(DEFAULT_SSH_COMMAND, "/mnt/john's keys/work", r"ssh -i '/mnt/john'\''s keys/work' -o IdentitiesOnly=yes")]
+        fn render_ssh_command(#[case] template: &str, #[case] key: &str, #[case] expected: &str) {
+            let mut profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            profile.key = Some(PathBuf::from(key));
+
+            assert_that!(ssh_command(template, &profile)).is_equal_to(expected.to_string());
+        }
+
+        #[rstest]
+        fn set_known_hosts(profile: &Profile, fake_repo: TempDir, fake_home: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+            let known_hosts = crate::profile::model::known_hosts_path(&profile.name);
+            fs::write(&known_hosts, "").unwrap();
+
+            configure_user(profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("core.sshCommand").unwrap()).is_equal_to(&*format!(
+                "ssh -i '{}' -o IdentitiesOnly=yes -o UserKnownHostsFile='{known_hosts}'", profile.private_key()
+            ));
+        }
+
+        #[rstest]
+        fn set_ssh_command_from_settings(profile: &Profile, fake_repo: TempDir, fake_home: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+            let settings = Settings { ssh_command: Some("ssh -i {key} -p 2222".to_string()), ..Settings::default() };
+            settings.save().unwrap();
+
+            configure_user(profile, Mode::Keys(Scope::Local)).unwrap();
+            let config = Config::open(&fake_repo.path().join(".git/config")).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("core.sshCommand").unwrap())
+                .is_equal_to(&*format!("ssh -i '{}' -p 2222", profile.private_key()));
         }
 
         #[rstest]
@@ -146,7 +727,7 @@ mod test {
             env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
             fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
 
-            configure_user(profile, true).unwrap();
+            configure_user(profile, Mode::Keys(Scope::Global)).unwrap();
             let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap()
                 .snapshot().unwrap();
 
@@ -155,7 +736,7 @@ mod test {
             assert_that!(config.get_str("user.email").unwrap())
                 .is_equal_to(&*profile.email);
             assert_that!(config.get_str("core.sshCommand").unwrap())
-                .is_equal_to(&*ssh_command(&profile.name));
+                .is_equal_to(&*ssh_command(DEFAULT_SSH_COMMAND, profile));
         }
 
         #[rstest]
@@ -164,7 +745,7 @@ mod test {
 
             fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
 
-            configure_user(profile, true).unwrap();
+            configure_user(profile, Mode::Keys(Scope::Global)).unwrap();
             let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap()
                 .snapshot().unwrap();
 
@@ -173,7 +754,395 @@ mod test {
             assert_that!(config.get_str("user.email").unwrap())
                 .is_equal_to(&*profile.email);
             assert_that!(config.get_str("core.sshCommand").unwrap())
-                .is_equal_to(&*ssh_command(&profile.name));
+                .is_equal_to(&*ssh_command(DEFAULT_SSH_COMMAND, profile));
+        }
+    }
+
+    mod alias_remotes {
+        use spectral::prelude::ResultAssertions;
+
+        use super::*;
+
+        #[fixture]
+        fn fake_repo() -> TempDir {
+            let fake_repo = tempdir().unwrap();
+            Repository::init(fake_repo.path()).unwrap();
+
+            fake_repo
+        }
+
+        #[rstest]
+        fn replace_other_aliases(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let config_path = fake_repo.path().join(".git/config");
+            let mut config = Config::open(&config_path).unwrap();
+            config.set_str("core.sshCommand", "ssh -i /tmp/key").unwrap();
+            config.set_str("url.git@github.com-oss:.insteadOf", "git@github.com:").unwrap();
+            config.set_str("url.https://mirror.example.com/.insteadOf", "https://example.com/").unwrap();
+            let profile = Profile::new("work", "Work", "em@i.l").unwrap();
+
+            alias_remotes(&profile, Scope::Local, &["github.com".to_string()]).unwrap();
+            let config = Config::open(&config_path).unwrap().snapshot().unwrap();
+
+            assert_that!(config.get_str("url.git@github.com-work:.insteadof").unwrap()).is_equal_to("git@github.com:");
+            assert_that!(config.get_str("url.ssh://git@github.com-work/.insteadof").unwrap())
+                .is_equal_to("ssh://git@github.com/");
+            assert_that!(config.get_str("url.https://mirror.example.com/.insteadof").unwrap())
+                .is_equal_to("https://example.com/");
+            assert_that!(config.get_str("url.git@github.com-oss:.insteadof")).is_err();
+            assert_that!(config.get_str("core.sshCommand")).is_err();
+        }
+
+        #[rstest]
+        fn keep_other_values_of_replaced_alias(fake_repo: TempDir) {
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let config_path = fake_repo.path().join(".git/config");
+            let mut config = Config::open(&config_path).unwrap();
+            config.set_multivar("url.git@github.com-oss:.insteadOf", "^$", "git@github.com:").unwrap();
+            config.set_multivar("url.git@github.com-oss:.insteadOf", "^$", "git@github-com:").unwrap();
+            config.set_multivar("url.git@github.com-oss:.insteadOf", "^$", "git@(github.com):").unwrap();
+            let profile = Profile::new("work", "Work", "em@i.l").unwrap();
+
+            alias_remotes(&profile, Scope::Local, &["github.com".to_string()]).unwrap();
+            let config = Config::open(&config_path).unwrap().snapshot().unwrap();
+
+            let mut values = vec![];
+            let mut entries = config.multivar("url.git@github.com-oss:.insteadof", None).unwrap();
+            while let Some(entry) = entries.next() {
+                values.push(entry.unwrap().value().unwrap().to_string());
+            }
+            assert_that!(values).is_equal_to(vec!["git@github-com:".to_string(), "git@(github.com):".to_string()]);
+        }
+    }
+
+    mod discovery {
+        use std::path::Path;
+
+        use git2::{RepositoryInitOptions, Signature, WorktreeAddOptions};
+
+        use super::*;
+
+        #[fixture]
+        fn profile() -> Profile {
+            Profile::new("test", "Test Profile", "em@i.l").unwrap()
+        }
+
+        #[fixture]
+        fn fake_repo() -> TempDir {
+            let fake_repo = tempdir().unwrap();
+            let repo = Repository::init(fake_repo.path()).unwrap();
+            // worktrees can only be created off a commit
+            let signature = Signature::now("Test Profile", "em@i.l").unwrap();
+            let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+            repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[]).unwrap();
+
+            fake_repo
+        }
+
+        fn assert_configured(config_path: &Path, profile: &Profile) {
+            let config = Config::open(config_path).unwrap()
+                .snapshot().unwrap();
+
+            assert_that!(config.get_str("user.name").unwrap())
+                .is_equal_to(&*profile.username);
+            assert_that!(config.get_str("user.email").unwrap())
+                .is_equal_to(&*profile.email);
+        }
+
+        #[rstest]
+        fn subdirectory(profile: Profile, fake_repo: TempDir) {
+            let subdir = fake_repo.path().join("some/nested/dir");
+            fs::create_dir_all(&subdir).unwrap();
+            env::set_current_dir(&subdir).unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+
+            assert_configured(&fake_repo.path().join(".git/config"), &profile);
+        }
+
+        #[rstest]
+        fn worktree(profile: Profile, fake_repo: TempDir) {
+            let worktree_dir = tempdir().unwrap();
+            let worktree_path = worktree_dir.path().join("wt");
+            Repository::open(fake_repo.path()).unwrap()
+                .worktree("wt", &worktree_path, Some(&WorktreeAddOptions::new()))
+                .unwrap();
+            env::set_current_dir(&worktree_path).unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+
+            assert_configured(&fake_repo.path().join(".git/config"), &profile);
+        }
+
+        #[rstest]
+        fn submodule(profile: Profile, fake_repo: TempDir) {
+            let module_git_dir = fake_repo.path().join(".git/modules/sub");
+            let module_path = fake_repo.path().join("sub");
+            let mut opts = RepositoryInitOptions::new();
+            opts.workdir_path(&module_path).no_dotgit_dir(true);
+            Repository::init_opts(&module_git_dir, &opts).unwrap();
+            env::set_current_dir(&module_path).unwrap();
+
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+
+            assert_configured(&module_git_dir.join("config"), &profile);
+        }
+
+        #[rstest]
+        fn git_dir_env(profile: Profile, fake_repo: TempDir) {
+            let outside = tempdir().unwrap();
+            env::set_current_dir(outside.path()).unwrap();
+            env::set_var("GIT_DIR", fake_repo.path().join(".git"));
+
+            let result = configure_user(&profile, Mode::Keys(Scope::Local));
+            env::remove_var("GIT_DIR");
+
+            result.unwrap();
+            assert_configured(&fake_repo.path().join(".git/config"), &profile);
+        }
+
+        #[rstest]
+        fn worktree_scope(profile: Profile, fake_repo: TempDir) {
+            let other = Profile::new("other", "Other Profile", "other@i.l").unwrap();
+            let worktree_dir = tempdir().unwrap();
+            let repo = Repository::open(fake_repo.path()).unwrap();
+            for name in ["wt1", "wt2"] {
+                repo.worktree(name, &worktree_dir.path().join(name), Some(&WorktreeAddOptions::new()))
+                    .unwrap();
+            }
+
+            env::set_current_dir(worktree_dir.path().join("wt1")).unwrap();
+            configure_user(&profile, Mode::Keys(Scope::Worktree)).unwrap();
+            env::set_current_dir(worktree_dir.path().join("wt2")).unwrap();
+            configure_user(&other, Mode::Keys(Scope::Worktree)).unwrap();
+
+            let git_dir = fake_repo.path().join(".git");
+            assert_configured(&git_dir.join("worktrees/wt1/config.worktree"), &profile);
+            assert_configured(&git_dir.join("worktrees/wt2/config.worktree"), &other);
+            let repo_config = Config::open(&git_dir.join("config")).unwrap()
+                .snapshot().unwrap();
+            assert_that!(repo_config.get_bool("extensions.worktreeConfig").unwrap()).is_equal_to(true);
+            assert_that!(get_username_and_email(Scope::Worktree).unwrap())
+                .is_equal_to((other.username.to_string(), other.email.to_string()));
+        }
+    }
+
+    mod resolution {
+        use std::path::Path;
+
+        use super::*;
+
+        #[rstest]
+        #[case::only_home(true, false, ".gitconfig")]
+        #[case::both(true, true, ".gitconfig")]
+        #[case::only_xdg(false, true, "xdg/git/config")]
+        #[case::none(false, false, ".gitconfig")]
+        fn global_config(#[case] home_exists: bool, #[case] xdg_exists: bool, #[case] expected: &str) {
+            let fake_home = tempdir().unwrap();
+            let xdg_config_home = fake_home.path().join("xdg");
+            if home_exists {
+                fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
+            }
+            if xdg_exists {
+                fs::create_dir_all(xdg_config_home.join("git")).unwrap();
+                fs::write(xdg_config_home.join("git/config"), "").unwrap();
+            }
+
+            let path = global_config_path(fake_home.path(), Some(xdg_config_home), None);
+
+            assert_that!(path).is_equal_to(fake_home.path().join(expected));
+        }
+
+        #[test]
+        fn global_config_env() {
+            let fake_home = tempdir().unwrap();
+            fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
+            let config_global = fake_home.path().join("custom");
+
+            let path = global_config_path(fake_home.path(), None, Some(config_global.clone()));
+
+            assert_that!(path).is_equal_to(config_global);
+        }
+
+        #[test]
+        fn user_configs() {
+            let home = Path::new("/home/user");
+
+            assert_that!(user_config_paths(home, None, None)).is_equal_to(vec![
+                (home.join(".config/git/config"), ConfigLevel::XDG),
+                (home.join(".gitconfig"), ConfigLevel::Global),
+            ]);
+        }
+
+        #[test]
+        fn user_configs_env() {
+            let home = Path::new("/home/user");
+            let config_global = PathBuf::from("/home/user/custom");
+
+            let paths = user_config_paths(home, Some(home.join("xdg")), Some(config_global.clone()));
+
+            assert_that!(paths).is_equal_to(vec![(config_global, ConfigLevel::Global)]);
+        }
+
+        #[test]
+        fn xdg_config_default() {
+            let home = Path::new("/home/user");
+
+            assert_that!(xdg_config_path(home, None)).is_equal_to(home.join(".config/git/config"));
+            assert_that!(xdg_config_path(home, Some(PathBuf::new()))).is_equal_to(home.join(".config/git/config"));
+        }
+
+        #[fixture]
+        fn fake_home() -> TempDir {
+            let fake_home = tempdir().unwrap();
+            fs::write(
+                fake_home.path().join(".gitconfig"),
+                "[user]\n\tname = Global Profile\n\temail = global@i.l\n",
+            ).unwrap();
+            env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+            fake_home
+        }
+
+        #[rstest]
+        fn effective_from_global(_fake_home: TempDir) {
+            let fake_repo = tempdir().unwrap();
+            Repository::init(fake_repo.path()).unwrap();
+            env::set_current_dir(fake_repo.path()).unwrap();
+
+            let identity = get_effective_username_and_email().unwrap();
+
+            assert_that!(identity).is_equal_to(("Global Profile".to_string(), "global@i.l".to_string()));
+        }
+
+        #[rstest]
+        fn effective_local_overrides_global(_fake_home: TempDir) {
+            let fake_repo = tempdir().unwrap();
+            Repository::init(fake_repo.path()).unwrap();
+            env::set_current_dir(fake_repo.path()).unwrap();
+            let profile = Profile::new("test", "Test Profile", "em@i.l").unwrap();
+            configure_user(&profile, Mode::Keys(Scope::Local)).unwrap();
+
+            let identity = get_effective_username_and_email().unwrap();
+
+            assert_that!(identity).is_equal_to((profile.username, profile.email));
+        }
+    }
+
+    mod rename_keys {
+        use super::*;
+
+        #[rstest]
+        fn global(#[from(fake_home_with_keys)] fake_home: TempDir) {
+            let mut old = Profile::new("old", "Test Profile", "em@i.l").unwrap();
+            old.sign = true;
+            configure_user(&old, Mode::Keys(Scope::Global)).unwrap();
+
+            let new = Profile::new("new", "Test Profile", "em@i.l").unwrap();
+
+            let changed = rename_keys("old", &new).unwrap();
+
+            assert_that!(changed).is_equal_to(vec![fake_home.path().join(".gitconfig")]);
+            let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap();
+            assert_that!(config.get_string("core.sshCommand").unwrap()).is_equal_to(ssh_command(DEFAULT_SSH_COMMAND, &new));
+            assert_that!(config.get_string("user.signingkey").unwrap()).is_equal_to(ssh::key::path_public("new"));
+        }
+
+        #[rstest]
+        fn other_profile(#[from(fake_home_with_keys)] fake_home: TempDir) {
+            let other = Profile::new("other", "Test Profile", "em@i.l").unwrap();
+            configure_user(&other, Mode::Keys(Scope::Global)).unwrap();
+
+            let new = Profile::new("new", "Test Profile", "em@i.l").unwrap();
+
+            assert_that!(rename_keys("old", &new).unwrap()).is_equal_to(vec![]);
+            let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap();
+            assert_that!(config.get_string("core.sshCommand").unwrap()).is_equal_to(ssh_command(DEFAULT_SSH_COMMAND, &other));
+        }
+
+        #[rstest]
+        fn known_hosts(#[from(fake_home_with_keys)] fake_home: TempDir) {
+            fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+            fs::write(known_hosts_path("old"), "").unwrap();
+            let old = Profile::new("old", "Test Profile", "em@i.l").unwrap();
+            configure_user(&old, Mode::Keys(Scope::Global)).unwrap();
+            fs::rename(known_hosts_path("old"), known_hosts_path("new")).unwrap();
+            let new = Profile::new("new", "Test Profile", "em@i.l").unwrap();
+
+            let changed = rename_keys("old", &new).unwrap();
+
+            assert_that!(changed).is_equal_to(vec![fake_home.path().join(".gitconfig")]);
+            let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap();
+            let command = config.get_string("core.sshCommand").unwrap();
+            assert_that!(command).is_equal_to(ssh_command(DEFAULT_SSH_COMMAND, &new));
+            assert_that!(command.contains(&known_hosts_path("new"))).is_equal_to(true);
+        }
+
+        #[rstest]
+        fn key_in_place(#[from(fake_home_with_keys)] fake_home: TempDir) {
+            let mut old = Profile::new("old", "Test Profile", "em@i.l").unwrap();
+            old.key = Some(PathBuf::from("/mnt/keys/work"));
+            configure_user(&old, Mode::Keys(Scope::Global)).unwrap();
+            let new = Profile { name: "new".to_string(), ..old.clone() };
+
+            assert_that!(rename_keys("old", &new).unwrap()).is_equal_to(vec![]);
+            let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap();
+            assert_that!(config.get_string("core.sshCommand").unwrap()).is_equal_to(ssh_command(DEFAULT_SSH_COMMAND, &old));
+        }
+
+        #[test]
+        fn quoted_home() {
+            let fake_home = Builder::new().prefix("john's home").tempdir().unwrap();
+            fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+            fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
+            env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+            env::set_current_dir(fake_home.path()).unwrap();
+            let old = Profile::new("old", "Test Profile", "em@i.l").unwrap();
+            configure_user(&old, Mode::Keys(Scope::Global)).unwrap();
+            let new = Profile::new("new", "Test Profile", "em@i.l").unwrap();
+
+            let changed = rename_keys("old", &new).unwrap();
+
+            assert_that!(changed).is_equal_to(vec![fake_home.path().join(".gitconfig")]);
+            let config = Config::open(&fake_home.path().join(".gitconfig")).unwrap();
+            assert_that!(config.get_string("core.sshCommand").unwrap()).is_equal_to(ssh_command(DEFAULT_SSH_COMMAND, &new));
+        }
+
+        #[rstest]
+        fn unquoted(#[from(fake_home_with_keys)] fake_home: TempDir) {
+            let config_path = fake_home.path().join(".gitconfig");
+            let command = |name: &str| format!("ssh -i {} -F /dev/null", ssh::key::path_private(name));
+            Config::open(&config_path).unwrap().set_str("core.sshCommand", &command("old")).unwrap();
+            let new = Profile::new("new", "Test Profile", "em@i.l").unwrap();
+
+            rename_keys("old", &new).unwrap();
+
+            assert_that!(Config::open(&config_path).unwrap().get_string("core.sshCommand").unwrap())
+                .is_equal_to(command("new"));
+        }
+
+        #[fixture]
+        fn fake_home_with_keys() -> TempDir {
+            let fake_home = tempdir().unwrap();
+            fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+            fs::write(fake_home.path().join(".gitconfig"), "").unwrap();
+            env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+            env::set_current_dir(fake_home.path()).unwrap();
+
+            fake_home
+        }
+
+        #[rstest]
+        #[case::separate("ssh -i /keys/id_test -F /dev/null", Some("/keys/id_test"))]
+        #[case::joined("ssh -i/keys/id_test", Some("/keys/id_test"))]
+        #[case::option("ssh -o IdentitiesOnly=yes -o IdentityFile=/keys/id_test", Some("/keys/id_test"))]
+        #[case::option_joined("ssh -oidentityfile=/keys/id_test", Some("/keys/id_test"))]
+        #[case::none("ssh -F /dev/null -o IdentitiesOnly=yes", None)]
+        #[case::single_quoted(r"ssh -i '/my keys/john'\''s' -F /dev/null", Some("/my keys/john's"))]
+        #[case::double_quoted(r#"ssh -o "IdentityFile=/my keys/\"id\"" -F /dev/null"#, Some(r#"/my keys/"id""#))]
+        #[case::escaped(r"ssh -i /my\ keys/id_test", Some("/my keys/id_test"))]
+        fn identity_file_from_command(#[case] command: &str, #[case] expected: Option<&str>) {
+            assert_that!(identity_file(command)).is_equal_to(expected.map(String::from));
         }
     }
 }
@@ -1,4 +1,5 @@
 use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use clap::Parser;
 
@@ -6,14 +7,21 @@ use crate::cli::pres::Presentation;
 use crate::cli::Cli;
 
 mod cli;
+mod doctor;
 mod git;
 mod profile;
+mod settings;
 mod ssh;
 
 fn home() -> String {
     env::var("HOME").unwrap()
 }
 
+/// Current unix timestamp in seconds
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
 fn main() {
     if let Err(err) = Cli::parse().present() {
         println!("{err}");
@@ -0,0 +1,107 @@
+use std::fs;
+
+use crate::profile::error::Error;
+use crate::profile::model::Profile;
+use crate::profile::{list, Result};
+use crate::settings::Settings;
+use crate::ssh;
+use crate::ssh::config::Identity;
+
+/// Re-writes `~/.ssh/config.d/g` with a `Host <host>-<profile>` block for every host in
+/// [`Settings::host_aliases`] and every profile, so that plain `ssh`, `scp` and other tools
+/// can pick profile's key by host alias, e.g. `git clone git@github.com-work:acme/app.git`.
+///
+/// The file is removed if no hosts are configured.
+///
+/// ```
+/// regenerate().expect("Can't write ssh host aliases");
+/// ```
+pub fn regenerate() -> Result<()> {
+    let hosts = Settings::load()?.host_aliases;
+    let path = ssh::config::path();
+    if hosts.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)
+                .map_err(|e| Error::Io(e, path))?;
+        }
+        return Ok(());
+    }
+    let identities = list()?
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .map(|profile| Identity {
+            private_key: profile.private_key(),
+            known_hosts: profile.known_hosts(),
+            name: profile.name,
+        })
+        .collect::<Vec<_>>();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| Error::Io(e, dir.to_path_buf()))?;
+    }
+    fs::write(&path, ssh::config::render(&hosts, &identities))
+        .map_err(|e| Error::Io(e, path))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::PathBuf;
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::prelude::PathAssertions;
+    use tempfile::{tempdir, TempDir};
+
+    use crate::profile::model;
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    fn regenerate_for_hosts(_fake_home: TempDir) {
+        let work = Profile::new("work", "Work", "work@email.com").unwrap();
+        let mut oss = Profile::new("oss", "Oss", "oss@email.com").unwrap();
+        oss.key = Some(PathBuf::from("/mnt/keys/oss"));
+        for profile in [&work, &oss] {
+            profile.clone().save(false).unwrap();
+        }
+        let hosts = vec!["github.com".to_string()];
+        Settings { host_aliases: hosts.clone(), ..Settings::default() }.save().unwrap();
+        fs::write(model::known_hosts_path("work"), "").unwrap();
+
+        regenerate().unwrap();
+
+        let identities = vec![
+            Identity { name: "oss".to_string(), private_key: "/mnt/keys/oss".to_string(), known_hosts: None },
+            Identity {
+                name: "work".to_string(),
+                private_key: ssh::key::path_private("work"),
+                known_hosts: Some(model::known_hosts_path("work")),
+            },
+        ];
+        assert_that!(fs::read_to_string(ssh::config::path()).unwrap())
+            .is_equal_to(ssh::config::render(&hosts, &identities));
+    }
+
+    #[rstest]
+    fn regenerate_without_hosts(_fake_home: TempDir) {
+        fs::create_dir_all(ssh::config::path().parent().unwrap()).unwrap();
+        fs::write(ssh::config::path(), "stale").unwrap();
+
+        regenerate().unwrap();
+
+        assert_that!(ssh::config::path()).does_not_exist();
+    }
+}
@@ -1,76 +1,372 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::fs;
-use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
 
 use crate::profile::error::Error;
 use crate::profile::model::Profile;
-use crate::profile::{profiles_dir, Result};
+use crate::profile::{list, profiles_dir, Result};
+
+/// Identity index, maps normalized identities (see [`key`]) to profile names
+type Index = BTreeMap<String, String>;
+
+/// Problem found in the identity index by [`verify`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// Profile isn't indexed under its identity
+    Missing { identity: String, profile: String },
+    /// Index entry points to a profile that doesn't exist or has a different identity
+    Stale { identity: String, profile: String },
+    /// Several profiles share the same identity, only one of them is indexed
+    Duplicate { identity: String, profiles: Vec<String> },
+    /// Profile file can't be read, so it can't be indexed
+    Unreadable { profile: String, cause: String },
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { identity, profile } => write!(f, "missing: {identity} -> '{profile}'"),
+            Self::Stale { identity, profile } => write!(f, "stale: {identity} -> '{profile}'"),
+            Self::Duplicate { identity, profiles } => write!(f, "duplicate: {identity} used by '{}'", profiles.join("', '")),
+            Self::Unreadable { profile, cause } => write!(f, "unreadable: '{profile}', cause: {cause}"),
+        }
+    }
+}
 
 pub(crate) fn get(username: &str, email: &str) -> Option<String> {
-    let mut cache = load().ok()?;
+    let mut index = load().ok()?;
+    let key = key(username, email);
+
+    index.remove(&key)
+}
+
+/// Outcome of looking up a profile by identity with [`lookup`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup {
+    /// Profile with exactly this identity
+    Exact(String),
+    /// No profile with this identity, but these ones have the same email and a different username
+    SameEmail(Vec<String>),
+    Unknown,
+}
+
+/// Finds profile with specified identity, using the index first and scanning profile files if it misses.
+/// A profile found by scanning is written to the index, so that next lookups don't need to scan.
+///
+/// Never fails, profiles that can't be read are just skipped
+///
+/// ```
+/// if let Lookup::Exact(profile) = lookup("Test Username", "test@email.com") {
+///     println!("{profile}");
+/// }
+/// ```
+pub fn lookup(username: &str, email: &str) -> Lookup {
     let key = key(username, email);
+    let indexed = get(username, email)
+        .and_then(|name| Profile::load(&name).ok())
+        .filter(|profile| self::key(&profile.username, &profile.email) == key);
+    if let Some(profile) = indexed {
+        return Lookup::Exact(profile.name);
+    }
+    let profiles = list().unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .collect::<Vec<_>>();
+    if let Some(profile) = profiles.iter().find(|p| self::key(&p.username, &p.email) == key) {
+        // repairing is best effort, the profile is found either way
+        let _ = insert(profile);
+        return Lookup::Exact(profile.name.to_string());
+    }
+    let email = email.trim().to_lowercase();
+    let same_email = profiles.into_iter()
+        .filter(|p| p.email.trim().to_lowercase() == email)
+        .map(|p| p.name)
+        .collect::<Vec<_>>();
 
-    cache.remove(&key)
+    if same_email.is_empty() { Lookup::Unknown } else { Lookup::SameEmail(same_email) }
 }
 
 pub(crate) fn get_all() -> Vec<String> {
     match load() {
-        Ok(cache) => cache
+        Ok(index) => index
             .into_values()
             .collect(),
         Err(_) => vec![]
     }
 }
 
+/// Indexes `profile` under its identity, dropping the identity it was indexed under before
 pub(super) fn insert(profile: &Profile) -> Result<()> {
-    let mut cache = load()?;
-    let key = key(&profile.username, &profile.email);
-    cache.insert(key, profile.name.to_string());
-    save(cache)?;
+    let mut index = load()?;
+    index.retain(|_, v| v != &profile.name);
+    index.insert(key(&profile.username, &profile.email), profile.name.to_string());
+    save(&index)?;
 
     Ok(())
 }
 
 pub(super) fn remove(profile_name: &str) -> Result<()> {
-    let cache = load()?
-        .into_iter()
-        .filter(|(_, v)| v != profile_name)
-        .collect::<HashMap<u64, String>>();
-    save(cache)?;
+    let mut index = load()?;
+    index.retain(|_, v| v != profile_name);
+    save(&index)?;
 
     Ok(())
 }
 
-fn key(username: &str, email: &str) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    username.hash(&mut hasher);
-    email.hash(&mut hasher);
+/// Re-creates the identity index from profile files, returns names of indexed profiles.
+/// Profiles that can't be read are skipped, if several profiles share an identity,
+/// the already indexed one stays, otherwise the first one is indexed.
+///
+/// ```
+/// let indexed = rebuild().expect("Can't rebuild identity index");
+/// ```
+pub fn rebuild() -> Result<Vec<String>> {
+    let (index, _) = scan(&load()?)?;
+    save(&index)?;
 
-    hasher.finish()
+    Ok(index.into_values().collect())
 }
 
-fn load() -> Result<HashMap<u64, String>> {
-    let cache_path = cache_path();
-    if !Path::new(&cache_path).exists() {
-        return Ok(HashMap::new());
+/// Compares the identity index with profile files and returns problems found
+///
+/// ```
+/// for issue in verify().expect("Can't verify identity index") {
+///     println!("{issue}");
+/// }
+/// ```
+pub fn verify() -> Result<Vec<Issue>> {
+    let actual = load()?;
+    let (expected, mut issues) = scan(&actual)?;
+    for (identity, profile) in &expected {
+        if actual.get(identity) != Some(profile) {
+            issues.push(Issue::Missing { identity: identity.to_string(), profile: profile.to_string() });
+        }
+    }
+    for (identity, profile) in actual {
+        if expected.get(&identity) != Some(&profile) {
+            issues.push(Issue::Stale { identity, profile });
+        }
     }
-    let bytes = fs::read(&cache_path)
-        .map_err(|e| Error::Io(e, cache_path.into()))?;
-    let cache = bincode::deserialize(&bytes[..])?;
 
-    Ok(cache)
+    Ok(issues)
 }
 
-fn save(cache: HashMap<u64, String>) -> Result<()> {
-    let cache_path = cache_path();
-    let bytes = bincode::serialize(&cache)?;
-    fs::write(&cache_path, &bytes[..])
-        .map_err(|e| Error::Io(e, cache_path.into()))?;
+/// Builds the index from profile files, reporting profiles that can't be indexed.
+/// If several profiles share an identity, the one from `current` index is kept.
+fn scan(current: &Index) -> Result<(Index, Vec<Issue>)> {
+    let mut index = Index::new();
+    let mut shared = BTreeMap::<String, Vec<String>>::new();
+    let mut issues = Vec::new();
+    for name in list()? {
+        match Profile::load(&name) {
+            Ok(profile) => shared.entry(key(&profile.username, &profile.email)).or_default().push(name),
+            Err(e) => issues.push(Issue::Unreadable { profile: name, cause: e.to_string() }),
+        }
+    }
+    for (identity, profiles) in shared {
+        let profile = current.get(&identity)
+            .filter(|profile| profiles.contains(profile))
+            .unwrap_or(&profiles[0]);
+        index.insert(identity.to_string(), profile.to_string());
+        if profiles.len() > 1 {
+            issues.push(Issue::Duplicate { identity, profiles });
+        }
+    }
+
+    Ok((index, issues))
+}
+
+/// Normalizes identity, so that it doesn't depend on surrounding whitespace and case of the email
+fn key(username: &str, email: &str) -> String {
+    let username = username.split_whitespace().collect::<Vec<_>>().join(" ");
+    let email = email.trim().to_lowercase();
+
+    format!("{username} <{email}>")
+}
+
+/// Loads the index, building it from profile files if it doesn't exist yet,
+/// e.g. after upgrading from a version of g that used the hash based cache
+fn load() -> Result<Index> {
+    let path = index_path();
+    if !Path::new(&path).exists() {
+        // saved upfront, so that profiles migrated while rebuilding don't trigger another rebuild
+        save(&Index::new())?;
+        rebuild()?;
+        let legacy = legacy_cache_path();
+        if Path::new(&legacy).exists() {
+            fs::remove_file(&legacy)
+                .map_err(|e| Error::Io(e, legacy.into()))?;
+        }
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| Error::Io(e, path.into()))?;
+    let index = toml::from_str(&content)?;
+
+    Ok(index)
+}
+
+fn save(index: &Index) -> Result<()> {
+    let path = index_path();
+    let content = toml::to_string(index)?;
+    fs::write(&path, content)
+        .map_err(|e| Error::Io(e, path.into()))?;
 
     Ok(())
 }
 
-fn cache_path() -> String {
+fn index_path() -> String {
+    format!("{}/.index.toml", profiles_dir())
+}
+
+/// Cache of profile names keyed by `DefaultHasher` hashes, used before the index
+fn legacy_cache_path() -> String {
     format!("{}/.cache", profiles_dir())
 }
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::prelude::{OptionAssertions, PathAssertions, VecAssertions};
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    #[case::same("Test Username", "test@email.com")]
+    #[case::whitespace("  Test   Username ", " test@email.com ")]
+    #[case::email_case("Test Username", "Test@Email.COM")]
+    fn normalized_identity(_fake_home: TempDir, #[case] username: &str, #[case] email: &str) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+
+        assert_that!(get(username, email)).is_equal_to(Some("test".to_string()));
+    }
+
+    #[rstest]
+    fn human_readable(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+
+        assert_that!(fs::read_to_string(index_path()).unwrap())
+            .is_equal_to("\"Test Username <test@email.com>\" = \"test\"\n".to_string());
+    }
+
+    #[rstest]
+    fn insert_replaces_old_identity(_fake_home: TempDir) {
+        let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+        profile.clone().save(false).unwrap();
+        profile.email = "new@email.com".to_string();
+        profile.save(true).unwrap();
+
+        assert_that!(get("Test Username", "test@email.com")).is_none();
+        assert_that!(get("Test Username", "new@email.com")).is_equal_to(Some("test".to_string()));
+    }
+
+    #[rstest]
+    fn lookup_indexed(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+
+        assert_that!(lookup("Test Username", "test@email.com")).is_equal_to(Lookup::Exact("test".to_string()));
+    }
+
+    #[rstest]
+    #[case::empty(Index::new())]
+    #[case::stale(Index::from([("Test Username <test@email.com>".to_string(), "other".to_string())]))]
+    fn lookup_repairs_index(_fake_home: TempDir, #[case] index: Index) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+        save(&index).unwrap();
+
+        assert_that!(lookup("Test Username", "test@email.com")).is_equal_to(Lookup::Exact("test".to_string()));
+        assert_that!(get("Test Username", "test@email.com")).is_equal_to(Some("test".to_string()));
+    }
+
+    #[rstest]
+    #[case::same_email("Other Username", "Test@email.com", Lookup::SameEmail(vec!["test".to_string()]))]
+    #[case::unknown("Test Username", "other@email.com", Lookup::Unknown)]
+    fn lookup_without_match(
+        _fake_home: TempDir, #[case] username: &str, #[case] email: &str, #[case] expected: Lookup,
+    ) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+
+        assert_that!(lookup(username, email)).is_equal_to(expected);
+    }
+
+    #[rstest]
+    fn rebuild_from_profiles(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+        save(&Index::from([("Stale <stale@email.com>".to_string(), "stale".to_string())])).unwrap();
+
+        let indexed = rebuild().unwrap();
+
+        assert_that!(indexed).is_equal_to(vec!["test".to_string()]);
+        assert_that!(get("Stale", "stale@email.com")).is_none();
+        assert_that!(get("Test Username", "test@email.com")).is_equal_to(Some("test".to_string()));
+    }
+
+    #[rstest]
+    fn rebuild_replaces_legacy_cache(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+        fs::remove_file(index_path()).unwrap();
+        fs::write(legacy_cache_path(), "").unwrap();
+
+        assert_that!(get("Test Username", "test@email.com")).is_equal_to(Some("test".to_string()));
+        assert_that!(Path::new(&legacy_cache_path())).does_not_exist();
+    }
+
+    #[rstest]
+    fn verify_up_to_date(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+
+        assert_that!(verify().unwrap()).is_empty();
+    }
+
+    #[rstest]
+    fn verify_outdated(_fake_home: TempDir) {
+        Profile::new("test", "Test Username", "test@email.com").unwrap()
+            .save(false).unwrap();
+        Profile::new("other", "Other Username", "other@email.com").unwrap()
+            .save(false).unwrap();
+        fs::write(profiles_dir() + "/duplicate.toml", "username = \"Test Username\"\nemail = \"test@email.com\"\n")
+            .unwrap();
+        fs::write(profiles_dir() + "/broken.toml", "not toml").unwrap();
+        save(&Index::from([
+            ("Test Username <test@email.com>".to_string(), "test".to_string()),
+            ("Stale <stale@email.com>".to_string(), "stale".to_string()),
+        ])).unwrap();
+
+        let issues = verify().unwrap();
+
+        assert_that!(issues.len()).is_equal_to(4);
+        assert_that!(issues.iter().any(|i| matches!(i, Issue::Unreadable { profile, .. } if profile == "broken")))
+            .is_equal_to(true);
+        assert_that!(issues.iter().any(|i| matches!(i, Issue::Duplicate { profiles, .. } if profiles.len() == 2)))
+            .is_equal_to(true);
+        assert_that!(issues.contains(&Issue::Missing {
+            identity: "Other Username <other@email.com>".to_string(),
+            profile: "other".to_string(),
+        })).is_equal_to(true);
+        assert_that!(issues.contains(&Issue::Stale {
+            identity: "Stale <stale@email.com>".to_string(),
+            profile: "stale".to_string(),
+        })).is_equal_to(true);
+    }
+}
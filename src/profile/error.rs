@@ -5,8 +5,10 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Profile name can't start with '.'")]
+    #[error("Profile name can't contain '.'")]
     InvalidName,
+    #[error("Profile name '{0}' is reserved")]
+    ReservedName(String),
     #[error(
     "Can't use username/email combination: {username}/{email}\nAlready in use by profile: '{existing}'"
     )]
@@ -17,8 +19,26 @@ pub enum Error {
     },
     #[error("Profile with name '{0}' already exists")]
     ProfileExists(String),
+    #[error("Profile with name '{0}' doesn't exist")]
+    NotFound(String),
+    #[error("Ssh key {0} already exists")]
+    KeyExists(String),
     #[error("{0}, path: {1}")]
     Io(#[source] io::Error, PathBuf),
     #[error(transparent)]
     Serde(#[from] bincode::Error),
+    #[error("Profile format version {0} isn't supported, it was probably saved by a newer version of g")]
+    UnsupportedVersion(String),
+    #[error("Identity index has {0} problem(s)")]
+    IndexOutdated(usize),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error(transparent)]
+    Settings(#[from] crate::settings::error::Error),
+    #[error(transparent)]
+    Ssh(#[from] crate::ssh::error::Error),
 }
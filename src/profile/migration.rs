@@ -0,0 +1,409 @@
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::profile::error::Error;
+use crate::profile::model::PartialProfile;
+use crate::profile::{profiles_dir, Result};
+use crate::settings::Store;
+
+/// Version of the format profiles are currently saved in, bump it when changing [`PartialProfile`]
+/// and add a [`Versioned`] variant with a migration for the previous one
+pub(super) const FORMAT_VERSION: u32 = 6;
+/// Prefix of binary profiles, followed by little endian `u32` format version
+const MAGIC: &[u8; 4] = b"gprf";
+const VERSION_KEY: &str = "version";
+
+/// Path of a profile saved by g 1.x, those were stored as json
+pub(super) fn json_path(profile_name: &str) -> String {
+    format!("{}/{profile_name}.json", profiles_dir())
+}
+
+/// Serializes profile in current format version, prefixed with the version
+pub(super) fn encode(store: Store, partial: &PartialProfile) -> Result<Vec<u8>> {
+    let bytes = match store {
+        Store::Toml => format!("{VERSION_KEY} = {FORMAT_VERSION}\n{}", toml::to_string(partial)?).into_bytes(),
+        Store::Bincode => {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(FORMAT_VERSION.to_le_bytes());
+            bytes.extend(bincode::serialize(partial)?);
+            bytes
+        }
+    };
+
+    Ok(bytes)
+}
+
+/// Deserializes profile saved in any format version and migrates it to the current one.
+/// Returns the profile and whether it was saved in an older format, so it should be re-written.
+///
+/// Binary profiles without a version prefix are the ones saved before formats were versioned,
+/// their version is guessed by trying the newest format first.
+/// Toml profiles without version are the ones saved before formats were versioned, all of them are in version 4.
+/// That's also the reason why fields added since then need `#[serde(default)]`.
+pub(super) fn decode(store: Store, bytes: &[u8]) -> Result<(PartialProfile, bool)> {
+    let versioned = match store {
+        Store::Toml => {
+            let mut table = toml::from_str::<toml::Table>(&String::from_utf8_lossy(bytes))?;
+            let version = match table.remove(VERSION_KEY) {
+                Some(value) => value.as_integer()
+                    .and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| Error::UnsupportedVersion(value.to_string()))?,
+                None => 4,
+            };
+            Versioned::decode(version, &TomlDecoder(table))?
+        }
+        Store::Bincode => match bytes.strip_prefix(MAGIC) {
+            Some(versioned) if versioned.len() >= 4 => {
+                let (version, payload) = versioned.split_at(4);
+                let version = u32::from_le_bytes(version.try_into().unwrap());
+                Versioned::decode(version, &BincodeDecoder(payload))?
+            }
+            _ => Versioned::guess(&BincodeDecoder(bytes))?,
+        }
+    };
+    let outdated = (store == Store::Bincode && !bytes.starts_with(MAGIC)) || versioned.version() < FORMAT_VERSION;
+
+    Ok((versioned.into_current(), outdated))
+}
+
+/// Deserializes profile saved by g 1.x and migrates it to the current format version
+pub(super) fn decode_json(bytes: &[u8]) -> Result<PartialProfile> {
+    let v1 = serde_json::from_slice::<V1>(bytes)?;
+
+    Ok(Versioned::V1(v1).into_current())
+}
+
+trait Decoder {
+    fn decode<T: DeserializeOwned>(&self) -> Result<T>;
+}
+
+struct BincodeDecoder<'a>(&'a [u8]);
+
+impl Decoder for BincodeDecoder<'_> {
+    fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(bincode::deserialize(self.0)?)
+    }
+}
+
+struct TomlDecoder(toml::Table);
+
+impl Decoder for TomlDecoder {
+    fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(self.0.clone().try_into()?)
+    }
+}
+
+/// Every format version profiles were ever saved in
+enum Versioned {
+    V1(V1),
+    V2(V2),
+    V3(V3),
+    V4(V4),
+    V5(V5),
+    V6(PartialProfile),
+}
+
+/// Format used before remote url patterns were introduced, also the one of g 1.x json profiles
+#[derive(Deserialize)]
+struct V1 {
+    username: String,
+    email: String,
+}
+
+/// Format used before signing was introduced
+#[derive(Deserialize)]
+struct V2 {
+    username: String,
+    email: String,
+    remotes: Vec<String>,
+}
+
+/// Format used before gpg keys were introduced
+#[derive(Deserialize)]
+struct V3 {
+    username: String,
+    email: String,
+    remotes: Vec<String>,
+    sign: bool,
+}
+
+/// Format used before ssh keys could be referenced in place
+#[derive(Deserialize)]
+struct V4 {
+    username: String,
+    email: String,
+    #[serde(default)]
+    remotes: Vec<String>,
+    #[serde(default)]
+    sign: bool,
+    #[serde(default)]
+    gpg_key: Option<String>,
+}
+
+/// Format used before key rotations were recorded
+#[derive(Deserialize)]
+struct V5 {
+    username: String,
+    email: String,
+    #[serde(default)]
+    remotes: Vec<String>,
+    #[serde(default)]
+    sign: bool,
+    #[serde(default)]
+    gpg_key: Option<String>,
+    #[serde(default)]
+    key: Option<PathBuf>,
+}
+
+impl Versioned {
+    fn decode(version: u32, decoder: &impl Decoder) -> Result<Self> {
+        let versioned = match version {
+            1 => Self::V1(decoder.decode()?),
+            2 => Self::V2(decoder.decode()?),
+            3 => Self::V3(decoder.decode()?),
+            4 => Self::V4(decoder.decode()?),
+            5 => Self::V5(decoder.decode()?),
+            6 => Self::V6(decoder.decode()?),
+            _ => Err(Error::UnsupportedVersion(version.to_string()))?,
+        };
+
+        Ok(versioned)
+    }
+
+    fn guess(decoder: &impl Decoder) -> Result<Self> {
+        (1..=FORMAT_VERSION).rev()
+            .map(|version| Self::decode(version, decoder))
+            .reduce(|newer, older| newer.or(older))
+            .unwrap()
+    }
+
+    fn version(&self) -> u32 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+            Self::V3(_) => 3,
+            Self::V4(_) => 4,
+            Self::V5(_) => 5,
+            Self::V6(_) => 6,
+        }
+    }
+
+    fn into_current(self) -> PartialProfile {
+        match self {
+            Self::V1(v1) => Self::V2(v1.into()).into_current(),
+            Self::V2(v2) => Self::V3(v2.into()).into_current(),
+            Self::V3(v3) => Self::V4(v3.into()).into_current(),
+            Self::V4(v4) => Self::V5(v4.into()).into_current(),
+            Self::V5(v5) => Self::V6(v5.into()).into_current(),
+            Self::V6(current) => current,
+        }
+    }
+}
+
+impl From<V1> for V2 {
+    fn from(v1: V1) -> Self {
+        Self {
+            username: v1.username,
+            email: v1.email,
+            remotes: vec![],
+        }
+    }
+}
+
+impl From<V2> for V3 {
+    fn from(v2: V2) -> Self {
+        Self {
+            username: v2.username,
+            email: v2.email,
+            remotes: v2.remotes,
+            sign: false,
+        }
+    }
+}
+
+impl From<V3> for V4 {
+    fn from(v3: V3) -> Self {
+        Self {
+            username: v3.username,
+            email: v3.email,
+            remotes: v3.remotes,
+            sign: v3.sign,
+            gpg_key: None,
+        }
+    }
+}
+
+impl From<V4> for V5 {
+    fn from(v4: V4) -> Self {
+        Self {
+            username: v4.username,
+            email: v4.email,
+            remotes: v4.remotes,
+            sign: v4.sign,
+            gpg_key: v4.gpg_key,
+            key: None,
+        }
+    }
+}
+
+impl From<V5> for PartialProfile {
+    fn from(v5: V5) -> Self {
+        Self {
+            username: v5.username,
+            email: v5.email,
+            remotes: v5.remotes,
+            sign: v5.sign,
+            gpg_key: v5.gpg_key,
+            key: v5.key,
+            key_created: None,
+            rotated: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use serde::Serialize;
+    use spectral::assert_that;
+    use spectral::prelude::BooleanAssertions;
+
+    use super::*;
+
+    const USERNAME: &str = "Test Username";
+    const EMAIL: &str = "test@email.com";
+    const REMOTE: &str = "git@github.com:acme/**";
+
+    fn with_header(version: u32, payload: &impl Serialize) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(version.to_le_bytes());
+        bytes.extend(bincode::serialize(payload).unwrap());
+        bytes
+    }
+
+    fn v1() -> (String, String) {
+        (USERNAME.to_string(), EMAIL.to_string())
+    }
+
+    fn v2() -> (String, String, Vec<String>) {
+        (USERNAME.to_string(), EMAIL.to_string(), vec![REMOTE.to_string()])
+    }
+
+    fn v3() -> (String, String, Vec<String>, bool) {
+        (USERNAME.to_string(), EMAIL.to_string(), vec![REMOTE.to_string()], true)
+    }
+
+    fn v4() -> (String, String, Vec<String>, bool, Option<String>) {
+        (USERNAME.to_string(), EMAIL.to_string(), vec![REMOTE.to_string()], true, Some("KEY".to_string()))
+    }
+
+    fn v5() -> (String, String, Vec<String>, bool, Option<String>, Option<PathBuf>) {
+        let (username, email, remotes, sign, gpg_key) = v4();
+        (username, email, remotes, sign, gpg_key, Some(PathBuf::from("/keys/test")))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn v6() -> (String, String, Vec<String>, bool, Option<String>, Option<PathBuf>, Option<u64>, Vec<u64>) {
+        let (username, email, remotes, sign, gpg_key, key) = v5();
+        (username, email, remotes, sign, gpg_key, key, Some(1_700_000_000), vec![1_700_000_000])
+    }
+
+    fn assert_migrated(partial: &PartialProfile, remotes: Vec<&str>, sign: bool, gpg_key: Option<&str>) {
+        assert_that!(partial.username.as_str()).is_equal_to(USERNAME);
+        assert_that!(partial.email.as_str()).is_equal_to(EMAIL);
+        assert_that!(partial.remotes).is_equal_to(remotes.into_iter().map(String::from).collect::<Vec<_>>());
+        assert_that!(partial.sign).is_equal_to(sign);
+        assert_that!(partial.gpg_key).is_equal_to(gpg_key.map(String::from));
+    }
+
+    #[rstest]
+    #[case::v1(bincode::serialize(&v1()).unwrap(), vec![], false, None)]
+    #[case::v2(bincode::serialize(&v2()).unwrap(), vec![REMOTE], false, None)]
+    #[case::v3(bincode::serialize(&v3()).unwrap(), vec![REMOTE], true, None)]
+    #[case::v4(bincode::serialize(&v4()).unwrap(), vec![REMOTE], true, Some("KEY"))]
+    fn bincode_without_header(
+        #[case] bytes: Vec<u8>, #[case] remotes: Vec<&str>, #[case] sign: bool, #[case] gpg_key: Option<&str>,
+    ) {
+        let (partial, outdated) = decode(Store::Bincode, &bytes).unwrap();
+
+        assert_migrated(&partial, remotes, sign, gpg_key);
+        assert_that!(outdated).is_true();
+    }
+
+    #[rstest]
+    #[case::v1(with_header(1, &v1()), vec![], false, None, true)]
+    #[case::v2(with_header(2, &v2()), vec![REMOTE], false, None, true)]
+    #[case::v3(with_header(3, &v3()), vec![REMOTE], true, None, true)]
+    #[case::v4(with_header(4, &v4()), vec![REMOTE], true, Some("KEY"), true)]
+    #[case::v5(with_header(5, &v5()), vec![REMOTE], true, Some("KEY"), true)]
+    #[case::v6(with_header(6, &v6()), vec![REMOTE], true, Some("KEY"), false)]
+    fn bincode_with_header(
+        #[case] bytes: Vec<u8>, #[case] remotes: Vec<&str>, #[case] sign: bool, #[case] gpg_key: Option<&str>,
+        #[case] expected_outdated: bool,
+    ) {
+        let (partial, outdated) = decode(Store::Bincode, &bytes).unwrap();
+
+        assert_migrated(&partial, remotes, sign, gpg_key);
+        assert_that!(outdated).is_equal_to(expected_outdated);
+    }
+
+    #[rstest]
+    #[case::v1("version = 1\nusername = \"Test Username\"\nemail = \"test@email.com\"\n", vec![], false, true)]
+    #[case::v2(
+        "version = 2\nusername = \"Test Username\"\nemail = \"test@email.com\"\nremotes = [\"git@github.com:acme/**\"]\n",
+        vec![REMOTE], false, true
+    )]
+    #[case::v3(
+        "version = 3\nusername = \"Test Username\"\nemail = \"test@email.com\"\nremotes = [\"git@github.com:acme/**\"]\nsign = true\n",
+        vec![REMOTE], true, true
+    )]
+    #[case::without_version("username = \"Test Username\"\nemail = \"test@email.com\"\n", vec![], false, true)]
+    #[case::v5("version = 5\nusername = \"Test Username\"\nemail = \"test@email.com\"\n", vec![], false, true)]
+    #[case::v6("version = 6\nusername = \"Test Username\"\nemail = \"test@email.com\"\n", vec![], false, false)]
+    fn toml(#[case] content: &str, #[case] remotes: Vec<&str>, #[case] sign: bool, #[case] expected_outdated: bool) {
+        let (partial, outdated) = decode(Store::Toml, content.as_bytes()).unwrap();
+
+        assert_migrated(&partial, remotes, sign, None);
+        assert_that!(outdated).is_equal_to(expected_outdated);
+    }
+
+    #[rstest]
+    fn json() {
+        let partial = decode_json(br#"{"username": "Test Username", "email": "test@email.com"}"#).unwrap();
+
+        assert_migrated(&partial, vec![], false, None);
+    }
+
+    #[rstest]
+    #[case::toml(Store::Toml)]
+    #[case::bincode(Store::Bincode)]
+    fn round_trip(#[case] store: Store) {
+        let partial = PartialProfile {
+            username: USERNAME.to_string(),
+            email: EMAIL.to_string(),
+            remotes: vec![REMOTE.to_string()],
+            sign: true,
+            gpg_key: Some("KEY".to_string()),
+            key: Some(PathBuf::from("/keys/test")),
+            key_created: Some(1_700_000_000),
+            rotated: vec![1_700_000_000],
+        };
+
+        let (decoded, outdated) = decode(store, &encode(store, &partial).unwrap()).unwrap();
+
+        assert_migrated(&decoded, vec![REMOTE], true, Some("KEY"));
+        assert_that!(decoded.key).is_equal_to(Some(PathBuf::from("/keys/test")));
+        assert_that!(decoded.key_created).is_equal_to(Some(1_700_000_000));
+        assert_that!(decoded.rotated).is_equal_to(vec![1_700_000_000]);
+        assert_that!(outdated).is_false();
+    }
+
+    #[rstest]
+    #[case::toml(Store::Toml, b"version = 99\nusername = \"\"\nemail = \"\"\n".to_vec())]
+    #[case::bincode(Store::Bincode, with_header(99, &v4()))]
+    fn unsupported_version(#[case] store: Store, #[case] bytes: Vec<u8>) {
+        assert_that!(decode(store, &bytes).is_err()).is_true();
+    }
+}
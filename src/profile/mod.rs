@@ -1,12 +1,19 @@
+use std::collections::BTreeSet;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::{home, ssh};
 use crate::profile::error::Error;
-use crate::profile::model::{Profile, profile_path};
+use crate::profile::model::{known_hosts_path, Profile, profile_path, stored_path, KNOWN_HOSTS_EXTENSION, TOML_EXTENSION};
+use crate::settings::{Settings, Store};
+use crate::ssh::key::policy::Violation;
 
+pub mod aliases;
 pub mod model;
 pub mod cache;
 pub mod error;
+mod migration;
+pub mod signers;
 
 type Result<T> = std::result::Result<T, error::Error>;
 
@@ -28,18 +35,29 @@ pub fn list() -> Result<Vec<String>> {
         .map(|dir_entry| dir_entry.unwrap().path())
         .filter(|path| path.is_file())
         .map(|path| path.components()
-            .last().unwrap()
+            .next_back().unwrap()
             .as_os_str()
             .to_string_lossy()
             .to_string()
         )
         .filter(|name| !name.starts_with('.'))
+        .filter(|name| name != signers::ALLOWED_SIGNERS)
+        .filter(|name| !name.ends_with(KNOWN_HOSTS_EXTENSION))
+        .map(|name| {
+            [TOML_EXTENSION, ".json"].into_iter()
+                .find_map(|extension| name.strip_suffix(extension))
+                .map(String::from)
+                .unwrap_or(name)
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
         .collect();
 
     Ok(names)
 }
 
-/// Removes profile with chosen `name` from [`PROFILES_DIR`] and profile cache.
+/// Removes profile with chosen `name` from [`PROFILES_DIR`] and profile cache, along with its ssh keys and known_hosts.
+/// Keys referenced in place ([`Profile::key`]) by this or any other profile aren't owned by it, so they're kept.
 ///
 /// ```
 /// let profile = "example";
@@ -47,7 +65,29 @@ pub fn list() -> Result<Vec<String>> {
 /// ```
 pub fn remove(name: &str) -> Result<Vec<String>> {
     let mut info = Vec::<String>::new();
-    [profile_path(name), ssh::key::path_private(name), ssh::key::path_public(name)]
+    let referenced = Profile::load(name).ok().filter(|profile| profile.key.is_some());
+    let mut paths = vec![
+        profile_path(name, Store::Toml),
+        profile_path(name, Store::Bincode),
+        migration::json_path(name),
+    ];
+    match referenced {
+        Some(profile) => info.extend([profile.private_key(), profile.public_key()].map(|p| format!("kept: {p}"))),
+        None if referenced_elsewhere(&ssh::key::path_private(name), name) => info.extend(
+            [ssh::key::path_private(name), ssh::key::path_public(name)].map(|p| format!("kept: {p}"))
+        ),
+        None => {
+            paths.extend([ssh::key::path_private(name), ssh::key::path_public(name)]);
+            let certificate = ssh::key::path_certificate_of(&ssh::key::path_private(name));
+            if Path::new(&certificate).exists() {
+                paths.push(certificate);
+            }
+        }
+    }
+    if Path::new(&known_hosts_path(name)).exists() {
+        paths.push(known_hosts_path(name));
+    }
+    paths
         .iter()
         .for_each(|p| match fs::remove_file(p) {
             Ok(_) => info.push(format!("removed: {p}")),
@@ -79,7 +119,313 @@ pub fn edit(name: &str, username: Option<String>, email: Option<String>) -> Resu
     profile.save(true)
 }
 
-fn profiles_dir() -> String {
+/// Adds and/or removes remote url patterns of profile with specified `name`.
+/// Patterns that are already present aren't duplicated.
+///
+/// ```
+/// let profile = "example";
+/// edit_remotes(profile, vec!["git@github.com:acme/**".to_string()], vec![]).expect(&format!("Can't edit {profile}"));
+/// ```
+pub fn edit_remotes(name: &str, add: Vec<String>, remove: Vec<String>) -> Result<()> {
+    if add.is_empty() && remove.is_empty() {
+        return Ok(());
+    }
+    let mut profile = Profile::load(name)?;
+    profile.remotes.retain(|r| !remove.contains(r));
+    for remote in add {
+        if !profile.remotes.contains(&remote) {
+            profile.remotes.push(remote);
+        }
+    }
+
+    profile.save(true)
+}
+
+/// Turns signing commits and tags on or off and/or changes gpg key for profile with specified `name`.
+/// `Some(None)` as `gpg_key` removes the gpg key, so that profile signs with its ssh key again.
+///
+/// ```
+/// let profile = "example";
+/// edit_signing(profile, Some(true), Some(Some("3AA5C34371567BD2".to_string()))).expect(&format!("Can't edit {profile}"));
+/// ```
+pub fn edit_signing(name: &str, sign: Option<bool>, gpg_key: Option<Option<String>>) -> Result<()> {
+    if sign.is_none() && gpg_key.is_none() {
+        return Ok(());
+    }
+    let mut profile = Profile::load(name)?;
+    if let Some(sign) = sign {
+        profile.sign = sign;
+    }
+    if let Some(gpg_key) = gpg_key {
+        profile.gpg_key = gpg_key;
+    }
+
+    profile.save(true)
+}
+
+/// Renames profile `old` to `new`, moving its file, known_hosts and ssh keys - the key pair (unless it's referenced
+/// in place), its certificate and keys retired by rotations - and updating the identity index.
+/// A key pair at `~/.ssh/id_<old>` another profile references in place isn't moved either,
+/// the renamed profile references it in place instead.
+/// If moving any of the files fails, the ones already moved are moved back.
+///
+/// # Errors
+/// - [`Error::ProfileExists`] if profile `new` already exists
+/// - [`Error::KeyExists`] if there already are ssh keys named after profile `new`
+///
+/// ```
+/// let profile = rename("old", "new").expect("Can't rename profile");
+/// ```
+pub fn rename(old: &str, new: &str) -> Result<Profile> {
+    let store = Settings::load()?.store;
+    let mut profile = Profile::load(old)?;
+    Profile::new(new, &profile.username, &profile.email)?;
+    if stored_path(new, store).is_some() {
+        Err(Error::ProfileExists(new.to_string()))?
+    }
+    let (stored_in, path) = stored_path(old, store)
+        .ok_or_else(|| Error::NotFound(old.to_string()))?;
+    let keep_key = profile.key.is_none() && referenced_elsewhere(&profile.private_key(), old);
+    if keep_key {
+        profile.key = Some(PathBuf::from(profile.private_key()));
+    }
+    if profile.key.is_none() {
+        ensure_no_keys(new)?;
+    }
+    let mut moves = vec![
+        (path, profile_path(new, stored_in)),
+        (known_hosts_path(old), known_hosts_path(new)),
+    ];
+    moves.extend(own_key_files(&profile, old).into_iter().zip(own_key_files(&profile, new)));
+    let mut moved: Vec<&(String, String)> = vec![];
+    for paths @ (from, to) in moves.iter().filter(|(from, _)| Path::new(from).exists()) {
+        if let Err(e) = fs::rename(from, to) {
+            // best effort, the original error is what matters
+            moved.iter().rev().for_each(|(from, to)| { let _ = fs::rename(to, from); });
+            Err(Error::Io(e, from.into()))?
+        }
+        moved.push(paths);
+    }
+    profile.name = new.to_string();
+    cache::remove(old)?;
+    if keep_key {
+        profile.clone().save(true)?;
+    }
+    cache::insert(&profile)?;
+
+    Ok(profile)
+}
+
+/// Creates profile `dst` with identity and signing settings of profile `src`,
+/// `username` and/or `email` replace the copied ones. Remote url patterns aren't copied,
+/// so that repositories don't match both profiles, while its known_hosts is.
+/// With `keys`, ssh keys of `src` - the key pair, its certificate and keys retired by rotations -
+/// are copied to `~/.ssh/id_<dst>` as well, with comment of the public key set to email of `dst`.
+///
+/// # Errors
+/// - [`Error::ProfileExists`] if profile `dst` already exists
+/// - [`Error::CombinationExists`] if neither `username` nor `email` is changed
+/// - [`Error::KeyExists`] if `keys` is set and there already are ssh keys named after profile `dst`
+///
+/// ```
+/// let profile = clone("work", "oss", None, Some("me@example.com".to_string()), false).expect("Can't clone profile");
+/// ```
+pub fn clone(src: &str, dst: &str, username: Option<String>, email: Option<String>, keys: bool) -> Result<Profile> {
+    let source = Profile::load(src)?;
+    let mut profile = Profile::new(
+        dst,
+        &username.unwrap_or(source.username.clone()),
+        &email.unwrap_or(source.email.clone()),
+    )?;
+    profile.sign = source.sign;
+    profile.gpg_key = source.gpg_key.clone();
+    let mut copies = vec![(known_hosts_path(src), known_hosts_path(dst))];
+    if keys {
+        ensure_no_keys(dst)?;
+        profile.key_created = source.key_created;
+        profile.rotated = source.rotated.clone();
+        let source_files = key_files(&source.private_key()).into_iter().chain(retired_key_files(&source, src));
+        copies.extend(source_files.zip(own_key_files(&profile, dst)));
+    }
+    profile.clone().save(false)?;
+    for (from, to) in copies.iter().filter(|(from, _)| Path::new(from).exists()) {
+        fs::copy(from, to)
+            .map_err(|e| Error::Io(e, from.into()))?;
+    }
+    if keys {
+        set_public_comment(&profile)?;
+    }
+
+    Ok(profile)
+}
+
+/// Paths of ssh key files g owns for `profile` if it was named `name`: [`key_files`] of its key pair,
+/// unless it's referenced in place, followed by [`retired_key_files`]
+fn own_key_files(profile: &Profile, name: &str) -> Vec<String> {
+    let current = match profile.key {
+        Some(_) => vec![],
+        None => key_files(&ssh::key::path_private(name)).to_vec(),
+    };
+
+    [current, retired_key_files(profile, name)].concat()
+}
+
+/// [`key_files`] of every key pair retired by rotations of `profile` if it was named `name`, oldest first
+fn retired_key_files(profile: &Profile, name: &str) -> Vec<String> {
+    let private = ssh::key::path_private(name);
+    profile.rotated
+        .iter()
+        .flat_map(|timestamp| key_files(&ssh::key::path_retired(&private, *timestamp)))
+        .collect()
+}
+
+/// Private key at `private`, its public key and certificate
+fn key_files(private: &str) -> [String; 3] {
+    [private.to_string(), ssh::key::path_public_of(private), ssh::key::path_certificate_of(private)]
+}
+
+/// Sets comment of profile's public key to its email, re-generating it from the private key.
+/// A private key that can't be read is left alone.
+fn set_public_comment(profile: &Profile) -> Result<()> {
+    let private = profile.private_key();
+    if let Ok(public) = ssh::key::public_from_private_file(Path::new(&private), &profile.email) {
+        ssh::key::write_public_file(Path::new(&profile.public_key()), &public)?;
+    }
+
+    Ok(())
+}
+
+/// Whether private key at `private` is referenced in place ([`Profile::key`]) by a profile other than `name`
+fn referenced_elsewhere(private: &str, name: &str) -> bool {
+    list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|other| other != name)
+        .filter_map(|other| Profile::load(&other).ok())
+        .any(|other| other.key.is_some_and(|key| key == Path::new(private)))
+}
+
+fn ensure_no_keys(name: &str) -> Result<()> {
+    let existing = [ssh::key::path_private(name), ssh::key::path_public(name)]
+        .into_iter()
+        .find(|path| Path::new(path).exists());
+    match existing {
+        Some(path) => Err(Error::KeyExists(path)),
+        None => Ok(()),
+    }
+}
+
+/// Sets private ssh key of profile with specified `name` to be referenced in place,
+/// `None` makes the profile use `~/.ssh/id_<name>` again.
+/// Age of the key becomes unknown until [`record_key`] is called.
+///
+/// ```
+/// let profile = edit_key("example", Some(PathBuf::from("/home/user/.ssh/keys/work"))).expect("Can't edit profile");
+/// ```
+pub fn edit_key(name: &str, key: Option<PathBuf>) -> Result<Profile> {
+    let mut profile = Profile::load(name)?;
+    profile.key = key;
+    profile.key_created = None;
+    profile.clone().save(true)?;
+
+    Ok(profile)
+}
+
+/// Stops profile with specified `name` from referencing its ssh key in place ([`Profile::key`]),
+/// so that a new key can be generated in `~/.ssh` without retiring or overwriting a key g doesn't own.
+/// The referenced key itself is left alone.
+///
+/// Returns the profile and the key it referenced, if any.
+///
+/// ```
+/// let (profile, referenced) = detach_key("example").expect("Can't edit profile");
+/// ```
+pub fn detach_key(name: &str) -> Result<(Profile, Option<PathBuf>)> {
+    let profile = Profile::load(name)?;
+    match profile.key {
+        Some(key) => Ok((edit_key(name, None)?, Some(key))),
+        None => Ok((profile, None)),
+    }
+}
+
+/// Records that g generated a new ssh key for profile with specified `name` at `created` (unix timestamp).
+/// If the key replaced an older one, `rotation` adds the date to profile's rotation history.
+///
+/// ```
+/// let profile = record_key("example", 1700000000, true).expect("Can't record key rotation");
+/// ```
+pub fn record_key(name: &str, created: u64, rotation: bool) -> Result<Profile> {
+    let mut profile = Profile::load(name)?;
+    profile.key_created = Some(created);
+    if rotation {
+        profile.rotated.push(created);
+    }
+    profile.clone().save(true)?;
+
+    Ok(profile)
+}
+
+/// Checks ssh keys of every profile against the key policy from settings.
+/// Returns names of profiles whose keys violate it, profiles without readable keys are skipped.
+///
+/// ```
+/// for (name, violation) in audit_keys().expect("Can't audit keys") {
+///     println!("{name}: {violation}");
+/// }
+/// ```
+pub fn audit_keys() -> Result<Vec<(String, Violation)>> {
+    let policy = Settings::load()?.key_policy;
+    let violations = list()?
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .filter_map(|profile| {
+            let violation = policy.audit(&profile.read_public_key()?)?;
+            Some((profile.name, violation))
+        })
+        .collect();
+
+    Ok(violations)
+}
+
+/// Switches the [`Store`] profiles are saved in and converts every existing profile to it.
+/// Returns names of converted profiles.
+///
+/// ```
+/// let converted = convert(Store::Toml).expect("Can't convert profiles");
+/// ```
+pub fn convert(store: Store) -> Result<Vec<String>> {
+    let mut settings = Settings::load()?;
+    settings.store = store;
+    settings.save()?;
+    let mut converted = Vec::new();
+    for name in list()? {
+        let outdated = stored_path(&name, store).is_none_or(|(stored_in, _)| stored_in != store);
+        // loading converts the profile if it's saved in a different store
+        Profile::load(&name)?;
+        if outdated {
+            converted.push(name);
+        }
+    }
+
+    Ok(converted)
+}
+
+/// Returns path of the file profile with specified `name` is saved to
+///
+/// ```
+/// let profile = "example";
+/// println!("{}", path(profile).expect(&format!("Can't find {profile}")));
+/// ```
+pub fn path(name: &str) -> Result<String> {
+    // loading migrates profile if needed, so that the path of the up to date file is returned
+    Profile::load(name)?;
+    let store = Settings::load()?.store;
+    stored_path(name, store)
+        .map(|(_, path)| path)
+        .ok_or_else(|| Error::NotFound(name.to_string()))
+}
+
+pub(crate) fn profiles_dir() -> String {
     format!("{}/{PROFILES_DIR}", home())
 }
 
@@ -111,8 +457,10 @@ mod test {
 
         #[rstest]
         fn empty(fake_home: TempDir) {
-            // create a hidden file to test it doesn't get picked up
-            fs::write(&fake_home.path().join(PROFILES_DIR).join(".hidden"), "").unwrap();
+            // create a hidden file, allowed signers and known_hosts to test they don't get picked up
+            fs::write(fake_home.path().join(PROFILES_DIR).join(".hidden"), "").unwrap();
+            fs::write(fake_home.path().join(PROFILES_DIR).join(signers::ALLOWED_SIGNERS), "").unwrap();
+            fs::write(known_hosts_path("test"), "").unwrap();
 
             assert_that!(list().unwrap()).is_empty();
         }
@@ -127,6 +475,228 @@ mod test {
             assert_that!(list().unwrap())
                 .contains_all_of(&profiles.iter());
         }
+
+        #[rstest]
+        fn toml(fake_home: TempDir) {
+            // profile left in several formats is listed once
+            fs::write(fake_home.path().join(PROFILES_DIR).join("test.toml"), "").unwrap();
+            fs::write(fake_home.path().join(PROFILES_DIR).join("test.json"), "").unwrap();
+            fs::write(fake_home.path().join(PROFILES_DIR).join("test"), "").unwrap();
+
+            assert_that!(list().unwrap()).is_equal_to(vec!["test".to_string()]);
+        }
+    }
+
+    mod rename {
+        use super::*;
+
+        #[rstest]
+        fn ok(fake_home: TempDir) {
+            let old = Profile::new("old", "Test Username", "test@email.com").unwrap();
+            old.clone().save(false).unwrap();
+            fs::write(ssh::key::path_private("old"), "private").unwrap();
+            fs::write(ssh::key::path_public("old"), "public").unwrap();
+            fs::write(known_hosts_path("old"), "known hosts").unwrap();
+            fs::write(ssh::key::path_certificate_of(&ssh::key::path_private("old")), "certificate").unwrap();
+
+            let renamed = rename("old", "new").unwrap();
+
+            assert_that!(renamed.name.as_str()).is_equal_to("new");
+            assert_that!(Profile::load("new").unwrap()).is_equal_to(&renamed);
+            assert_that!(Profile::load("old").is_err()).is_equal_to(true);
+            assert_that!(fs::read_to_string(ssh::key::path_private("new")).unwrap()).is_equal_to("private".to_string());
+            assert_that!(fs::read_to_string(ssh::key::path_public("new")).unwrap()).is_equal_to("public".to_string());
+            assert_that!(fake_home.path().join(".ssh/id_old")).does_not_exist();
+            assert_that!(fs::read_to_string(known_hosts_path("new")).unwrap()).is_equal_to("known hosts".to_string());
+            assert_that!(Path::new(&known_hosts_path("old"))).does_not_exist();
+            assert_that!(fs::read_to_string(fake_home.path().join(".ssh/id_new-cert.pub")).unwrap())
+                .is_equal_to("certificate".to_string());
+            assert_that!(cache::get(&old.username, &old.email)).is_equal_to(Some("new".to_string()));
+        }
+
+        #[rstest]
+        fn key_in_place(_fake_home: TempDir) {
+            let mut old = Profile::new("old", "Test Username", "test@email.com").unwrap();
+            old.key = Some(PathBuf::from("/mnt/keys/work"));
+            old.save(false).unwrap();
+            fs::write(ssh::key::path_private("new"), "unrelated").unwrap();
+
+            let renamed = rename("old", "new").unwrap();
+
+            assert_that!(renamed.key).is_equal_to(Some(PathBuf::from("/mnt/keys/work")));
+            assert_that!(fs::read_to_string(ssh::key::path_private("new")).unwrap()).is_equal_to("unrelated".to_string());
+        }
+
+        #[rstest]
+        fn key_referenced_elsewhere(_fake_home: TempDir) {
+            Profile::new("old", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+            let mut other = Profile::new("other", "Other Username", "other@email.com").unwrap();
+            other.key = Some(PathBuf::from(ssh::key::path_private("old")));
+            other.save(false).unwrap();
+            fs::write(ssh::key::path_private("old"), "private").unwrap();
+            fs::write(ssh::key::path_public("old"), "public").unwrap();
+
+            let renamed = rename("old", "new").unwrap();
+
+            assert_that!(Path::new(&ssh::key::path_private("old"))).exists();
+            assert_that!(Path::new(&ssh::key::path_public("old"))).exists();
+            assert_that!(Path::new(&ssh::key::path_private("new"))).does_not_exist();
+            assert_that!(renamed.key).is_equal_to(Some(PathBuf::from(ssh::key::path_private("old"))));
+            assert_that!(Profile::load("new").unwrap()).is_equal_to(&renamed);
+        }
+
+        #[rstest]
+        fn retired_keys(fake_home: TempDir) {
+            let mut old = Profile::new("old", "Test Username", "test@email.com").unwrap();
+            old.rotated = vec![1_700_000_000];
+            old.save(false).unwrap();
+            for file in ["id_old.1700000000.old", "id_old.1700000000.old.pub", "id_old.1700000000.old-cert.pub"] {
+                fs::write(fake_home.path().join(".ssh").join(file), file).unwrap();
+            }
+
+            rename("old", "new").unwrap();
+
+            for (old, new) in [
+                ("id_old.1700000000.old", "id_new.1700000000.old"),
+                ("id_old.1700000000.old.pub", "id_new.1700000000.old.pub"),
+                ("id_old.1700000000.old-cert.pub", "id_new.1700000000.old-cert.pub"),
+            ] {
+                assert_that!(fake_home.path().join(".ssh").join(old)).does_not_exist();
+                assert_that!(fs::read_to_string(fake_home.path().join(".ssh").join(new)).unwrap())
+                    .is_equal_to(old.to_string());
+            }
+        }
+
+        #[rstest]
+        #[case::profile_exists(".config/g-profiles/new.toml")]
+        #[case::key_exists(".ssh/id_new.pub")]
+        fn err(fake_home: TempDir, #[case] existing: &str) {
+            Profile::new("old", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+            fs::write(ssh::key::path_private("old"), "private").unwrap();
+            fs::write(fake_home.path().join(existing), "").unwrap();
+
+            assert_that!(rename("old", "new").is_err()).is_equal_to(true);
+            assert_that!(Path::new(&ssh::key::path_private("old"))).exists();
+            assert_that!(Path::new(&profile_path("old", Store::Toml))).exists();
+        }
+    }
+
+    mod clone {
+        use rand::thread_rng;
+        use ssh_key::{Algorithm, LineEnding, PrivateKey, PublicKey};
+
+        use super::*;
+
+        #[fixture]
+        fn source() -> Profile {
+            let mut profile = Profile::new("src", "Test Username", "test@email.com").unwrap();
+            profile.remotes = vec!["git@github.com:acme/**".to_string()];
+            profile.sign = true;
+            profile.gpg_key = Some("KEY".to_string());
+            profile.clone().save(false).unwrap();
+            fs::write(ssh::key::path_private("src"), "private").unwrap();
+
+            profile
+        }
+
+        #[rstest]
+        #[case::without_keys(false)]
+        #[case::with_keys(true)]
+        fn ok(_fake_home: TempDir, #[from(source)] _source: Profile, #[case] keys: bool) {
+            let cloned = clone("src", "dst", None, Some("other@email.com".to_string()), keys).unwrap();
+
+            assert_that!(Profile::load("dst").unwrap()).is_equal_to(&cloned);
+            assert_that!(cloned.username.as_str()).is_equal_to("Test Username");
+            assert_that!(cloned.email.as_str()).is_equal_to("other@email.com");
+            assert_that!(cloned.remotes).is_empty();
+            assert_that!(cloned.sign).is_equal_to(true);
+            assert_that!(cloned.gpg_key).is_equal_to(Some("KEY".to_string()));
+            assert_that!(Path::new(&ssh::key::path_private("dst")).exists()).is_equal_to(keys);
+        }
+
+        #[rstest]
+        fn key_files(fake_home: TempDir, #[from(source)] mut source: Profile) {
+            let mut private = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+            private.set_comment("test@email.com");
+            private.write_openssh_file(Path::new(&ssh::key::path_private("src")), LineEnding::LF).unwrap();
+            ssh::key::write_public_file(Path::new(&ssh::key::path_public("src")), private.public_key()).unwrap();
+            source.rotated = vec![1_700_000_000];
+            source.save(true).unwrap();
+            for file in ["id_src-cert.pub", "id_src.1700000000.old", "id_src.1700000000.old.pub"] {
+                fs::write(fake_home.path().join(".ssh").join(file), file).unwrap();
+            }
+            fs::write(known_hosts_path("src"), "known hosts").unwrap();
+
+            let cloned = clone("src", "dst", None, Some("other@email.com".to_string()), true).unwrap();
+
+            assert_that!(cloned.rotated).is_equal_to(vec![1_700_000_000]);
+            for (src, dst) in [
+                ("id_src-cert.pub", "id_dst-cert.pub"),
+                ("id_src.1700000000.old", "id_dst.1700000000.old"),
+                ("id_src.1700000000.old.pub", "id_dst.1700000000.old.pub"),
+            ] {
+                assert_that!(fs::read_to_string(fake_home.path().join(".ssh").join(dst)).unwrap())
+                    .is_equal_to(src.to_string());
+            }
+            assert_that!(fs::read_to_string(known_hosts_path("dst")).unwrap()).is_equal_to("known hosts".to_string());
+            let public = PublicKey::read_openssh_file(Path::new(&ssh::key::path_public("dst"))).unwrap();
+            assert_that!(public.comment()).is_equal_to("other@email.com");
+            assert_that!(public.key_data()).is_equal_to(private.public_key().key_data());
+        }
+
+        #[rstest]
+        fn same_identity(_fake_home: TempDir, #[from(source)] _source: Profile) {
+            let err = clone("src", "dst", None, None, false).unwrap_err();
+
+            assert_that!(matches!(err, Error::CombinationExists { .. })).is_equal_to(true);
+            assert_that!(Profile::load("dst").is_err()).is_equal_to(true);
+        }
+    }
+
+    mod convert {
+        use super::*;
+
+        #[rstest]
+        #[case::to_bincode(Store::Bincode, "test.toml", "test")]
+        #[case::to_toml(Store::Toml, "test", "test.toml")]
+        fn ok(_fake_home: TempDir, #[case] store: Store, #[case] from: &str, #[case] to: &str) {
+            let profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.clone().save(false).unwrap();
+            if store == Store::Toml {
+                convert(Store::Bincode).unwrap();
+            }
+
+            let converted = convert(store).unwrap();
+
+            assert_that!(converted).is_equal_to(vec!["test".to_string()]);
+            assert_that!(Settings::load().unwrap().store).is_equal_to(store);
+            assert_that!(Path::new(&profiles_dir()).join(from)).does_not_exist();
+            assert_that!(Path::new(&profiles_dir()).join(to)).exists();
+            assert_that!(Profile::load("test").unwrap()).is_equal_to(profile);
+        }
+    }
+
+    mod path {
+        use super::*;
+
+        #[rstest]
+        #[case::toml(Store::Toml, "test.toml")]
+        #[case::bincode(Store::Bincode, "test")]
+        fn existing(fake_home: TempDir, #[case] store: Store, #[case] file: &str) {
+            Settings { store, ..Settings::default() }.save().unwrap();
+            Profile::new("test", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+            let expected = fake_home.path().join(PROFILES_DIR).join(file);
+
+            assert_that!(path("test").unwrap()).is_equal_to(expected.to_string_lossy().to_string());
+        }
+
+        #[rstest]
+        fn missing(_fake_home: TempDir) {
+            assert_that!(path("test").is_err()).is_equal_to(true);
+        }
     }
 
     mod remove {
@@ -145,6 +715,14 @@ mod test {
             vec ! [".ssh/id_test", ".ssh/id_test.pub"],
             vec ! [".config/g-profiles/test"]
         )]
+        #[case::known_hosts(
+            vec ! [".config/g-profiles/test", ".config/g-profiles/test.known_hosts"],
+            vec ! [".ssh/id_test", ".ssh/id_test.pub"]
+        )]
+        #[case::certificate(
+            vec ! [".config/g-profiles/test", ".ssh/id_test", ".ssh/id_test-cert.pub"],
+            vec ! [".ssh/id_test.pub"]
+        )]
         fn remove_stuff(#[case] removed: Vec<&str>, #[case] skipped: Vec<&str>, fake_home: TempDir) {
             let p = Profile::new("test", "", "").unwrap();
             cache::insert(&p).unwrap();
@@ -168,6 +746,40 @@ mod test {
                     assert_that!(info).contains(format!("skipped: {}", p.display()));
                 });
         }
+
+        #[rstest]
+        fn keeps_referenced_keys(fake_home: TempDir) {
+            let key = fake_home.path().join("work");
+            fs::write(&key, "private").unwrap();
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.key = Some(key.clone());
+            profile.save(false).unwrap();
+
+            let info = remove("test").unwrap();
+
+            assert_that!(key).exists();
+            assert_that!(info).contains(format!("kept: {}", key.display()));
+            assert_that!(Profile::load("test").is_err()).is_equal_to(true);
+        }
+
+        #[rstest]
+        fn keeps_keys_referenced_elsewhere(_fake_home: TempDir) {
+            Profile::new("test", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+            let mut other = Profile::new("other", "Other Username", "other@email.com").unwrap();
+            other.key = Some(PathBuf::from(ssh::key::path_private("test")));
+            other.save(false).unwrap();
+            fs::write(ssh::key::path_private("test"), "private").unwrap();
+            fs::write(ssh::key::path_public("test"), "public").unwrap();
+
+            let info = remove("test").unwrap();
+
+            for key in [ssh::key::path_private("test"), ssh::key::path_public("test")] {
+                assert_that!(Path::new(&key)).exists();
+                assert_that!(info).contains(format!("kept: {key}"));
+            }
+            assert_that!(Profile::load("test").is_err()).is_equal_to(true);
+        }
     }
 
     mod edit {
@@ -211,4 +823,178 @@ mod test {
             }
         }
     }
+
+    mod edit_key {
+        use super::*;
+
+        #[rstest]
+        #[case::set(Some(PathBuf::from("/mnt/keys/work")))]
+        #[case::reset(None)]
+        fn ok(_fake_home: TempDir, #[case] key: Option<PathBuf>) {
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.key = Some(PathBuf::from("/old/key"));
+            profile.save(false).unwrap();
+
+            edit_key("test", key.clone()).unwrap();
+
+            assert_that!(Profile::load("test").unwrap().key).is_equal_to(key);
+        }
+    }
+
+    mod detach_key {
+        use super::*;
+
+        #[rstest]
+        fn referenced(fake_home: TempDir) {
+            let key = fake_home.path().join("work");
+            fs::write(&key, "private").unwrap();
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.key = Some(key.clone());
+            profile.save(false).unwrap();
+
+            let (profile, referenced) = detach_key("test").unwrap();
+
+            assert_that!(referenced).is_equal_to(Some(key.clone()));
+            assert_that!(profile.key).is_none();
+            assert_that!(profile.private_key()).is_equal_to(ssh::key::path_private("test"));
+            assert_that!(Profile::load("test").unwrap().key).is_none();
+            assert_that!(fs::read_to_string(&key).unwrap()).is_equal_to("private".to_string());
+        }
+
+        #[rstest]
+        fn owned(_fake_home: TempDir) {
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.key_created = Some(1_700_000_000);
+            profile.clone().save(false).unwrap();
+
+            let (detached, referenced) = detach_key("test").unwrap();
+
+            assert_that!(referenced).is_none();
+            assert_that!(detached).is_equal_to(profile);
+        }
+    }
+
+    mod audit_keys {
+        use rand::thread_rng;
+        use ssh_key::private::DsaKeypair;
+        use ssh_key::{Algorithm, LineEnding, PrivateKey};
+
+        use super::*;
+
+        fn add(name: &str, private: PrivateKey) {
+            let profile = Profile::new(name, name, &format!("{name}@email.com")).unwrap();
+            profile.save(false).unwrap();
+            private.write_openssh_file(Path::new(&ssh::key::path_private(name)), LineEnding::LF).unwrap();
+        }
+
+        #[rstest]
+        fn dsa(fake_home: TempDir) {
+            let legacy = PrivateKey::from(DsaKeypair::random(&mut thread_rng()).unwrap());
+            // generating a dsa key takes long enough for tests running alongside to point HOME elsewhere
+            env::set_var("HOME", fake_home.path());
+            add("legacy", legacy);
+            add("modern", PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap());
+
+            let violations = audit_keys().unwrap();
+
+            assert_that!(violations).is_equal_to(vec![
+                ("legacy".to_string(), Violation::NotAllowed("dsa".to_string()))
+            ]);
+        }
+    }
+
+    mod record_key {
+        use super::*;
+
+        #[rstest]
+        #[case::generated(false, vec![])]
+        #[case::rotated(true, vec![1_700_000_000])]
+        fn ok(_fake_home: TempDir, #[case] rotation: bool, #[case] expected_rotated: Vec<u64>) {
+            Profile::new("test", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+
+            record_key("test", 1_700_000_000, rotation).unwrap();
+
+            let profile = Profile::load("test").unwrap();
+            assert_that!(profile.key_created).is_equal_to(Some(1_700_000_000));
+            assert_that!(profile.rotated).is_equal_to(expected_rotated);
+        }
+
+        #[rstest]
+        fn edit_key_resets_age(_fake_home: TempDir) {
+            Profile::new("test", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+            record_key("test", 1_700_000_000, true).unwrap();
+
+            edit_key("test", None).unwrap();
+
+            let profile = Profile::load("test").unwrap();
+            assert_that!(profile.key_created).is_none();
+            assert_that!(profile.rotated).is_equal_to(vec![1_700_000_000]);
+        }
+    }
+
+    mod edit_signing {
+        use super::*;
+
+        #[rstest]
+        #[case::on(Some(true), true)]
+        #[case::off(Some(false), false)]
+        #[case::unchanged(None, false)]
+        fn toggle_sign(_fake_home: TempDir, #[case] sign: Option<bool>, #[case] expected: bool) {
+            Profile::new("test", "Test Username", "test@email.com").unwrap()
+                .save(false).unwrap();
+
+            edit_signing("test", sign, None).unwrap();
+
+            assert_that!(Profile::load("test").unwrap().sign).is_equal_to(expected);
+        }
+
+        #[rstest]
+        #[case::set(Some(Some("NEWKEY")), Some("NEWKEY"))]
+        #[case::remove(Some(None), None)]
+        #[case::unchanged(None, Some("OLDKEY"))]
+        fn change_gpg_key(_fake_home: TempDir, #[case] gpg_key: Option<Option<&str>>, #[case] expected: Option<&str>) {
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.gpg_key = Some("OLDKEY".to_string());
+            profile.save(false).unwrap();
+
+            edit_signing("test", None, gpg_key.map(|k| k.map(String::from))).unwrap();
+
+            assert_that!(Profile::load("test").unwrap().gpg_key).is_equal_to(expected.map(String::from));
+        }
+    }
+
+    mod edit_remotes {
+        use super::*;
+
+        #[fixture]
+        fn profile() -> Profile {
+            let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+            profile.remotes = vec!["git@github.com:acme/**".to_string()];
+
+            profile
+        }
+
+        #[rstest]
+        #[case::add(vec!["git@github.com:corp/**"], vec![], vec!["git@github.com:acme/**", "git@github.com:corp/**"])]
+        #[case::add_duplicate(vec!["git@github.com:acme/**"], vec![], vec!["git@github.com:acme/**"])]
+        #[case::remove(vec![], vec!["git@github.com:acme/**"], vec![])]
+        #[case::replace(vec!["git@github.com:corp/**"], vec!["git@github.com:acme/**"], vec!["git@github.com:corp/**"])]
+        fn ok(
+            profile: Profile, _fake_home: TempDir,
+            #[case] add: Vec<&str>, #[case] remove: Vec<&str>, #[case] expected: Vec<&str>,
+        ) {
+            let name = profile.name.clone();
+            profile.save(false).unwrap();
+            let add = add.into_iter().map(String::from).collect();
+            let remove = remove.into_iter().map(String::from).collect();
+
+            edit_remotes(&name, add, remove).unwrap();
+
+            assert_that!(Profile::load(&name).unwrap().remotes).is_equal_to(
+                expected.into_iter().map(String::from).collect::<Vec<_>>()
+            );
+        }
+    }
 }
@@ -1,15 +1,37 @@
 use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
+use ssh_key::{Certificate, PublicKey};
 
-use crate::home;
-use crate::profile::{cache, profiles_dir, Result};
+use crate::{now, ssh};
+use crate::profile::{cache, migration, profiles_dir, signers, Result};
 use crate::profile::error::Error;
+use crate::settings::{Settings, Store};
 
-pub(super) fn profile_path(profile_name: &str) -> String {
-    format!("{}/{profile_name}", profiles_dir())
+pub(super) const TOML_EXTENSION: &str = ".toml";
+pub(super) const KNOWN_HOSTS_EXTENSION: &str = ".known_hosts";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub(super) fn profile_path(profile_name: &str, store: Store) -> String {
+    match store {
+        Store::Toml => format!("{}/{profile_name}{TOML_EXTENSION}", profiles_dir()),
+        Store::Bincode => format!("{}/{profile_name}", profiles_dir()),
+    }
+}
+
+/// Path of the known_hosts file owned by profile with specified name, whether it exists or not
+pub(crate) fn known_hosts_path(profile_name: &str) -> String {
+    format!("{}/{profile_name}{KNOWN_HOSTS_EXTENSION}", profiles_dir())
+}
+
+/// Finds the store profile with specified name is saved in, looking into `preferred` one first
+pub(super) fn stored_path(profile_name: &str, preferred: Store) -> Option<(Store, String)> {
+    [preferred, Store::Toml, Store::Bincode]
+        .into_iter()
+        .map(|store| (store, profile_path(profile_name, store)))
+        .find(|(_, path)| Path::new(path).exists())
 }
 
 /// Represents a g profile
@@ -18,95 +40,231 @@ pub struct Profile {
     pub name: String,
     pub username: String,
     pub email: String,
+    /// Remote url patterns (e.g. `git@github.com:acme/**`) the profile should be used for
+    pub remotes: Vec<String>,
+    /// Whether commits and tags should be signed with profile's signing key
+    pub sign: bool,
+    /// OpenPGP key id to sign with, profile's ssh key is used if not set
+    pub gpg_key: Option<String>,
+    /// Private ssh key referenced in place, `~/.ssh/id_<name>` is used if not set
+    pub key: Option<PathBuf>,
+    /// When g generated the current ssh key (unix timestamp), unknown for imported keys
+    pub key_created: Option<u64>,
+    /// When the ssh key was rotated (unix timestamps), oldest first
+    pub rotated: Vec<u64>,
 }
 
+/// [`Profile`] as it's saved to disk, its name is the name of the file
 #[derive(Serialize, Deserialize)]
-struct PartialProfile {
-    username: String,
-    email: String,
+pub(super) struct PartialProfile {
+    pub(super) username: String,
+    pub(super) email: String,
+    // defaults only matter for toml, so that hand-written profiles can skip them
+    #[serde(default)]
+    pub(super) remotes: Vec<String>,
+    #[serde(default)]
+    pub(super) sign: bool,
+    #[serde(default)]
+    pub(super) gpg_key: Option<String>,
+    #[serde(default)]
+    pub(super) key: Option<PathBuf>,
+    #[serde(default)]
+    pub(super) key_created: Option<u64>,
+    #[serde(default)]
+    pub(super) rotated: Vec<u64>,
 }
 
 impl Profile {
     /// Validates `name` and constructs [`Profile`]
     ///
-    /// [`Error::InvalidName`] is returned if `name` starts with the `.` character
+    /// [`Error::InvalidName`] is returned if `name` contains the `.` character, so that it can't be mistaken
+    /// for a file g keeps next to profiles (`<name>.toml`, `<name>.known_hosts`, ...) or for a retired key,
+    /// [`Error::ReservedName`] if it's the name of a file g keeps next to profiles
     ///
     /// ```
     /// let profile = Profile::new("example", "An example profile", "profile@example.com").unwrap();
     /// ```
     pub fn new(name: &str, username: &str, email: &str) -> Result<Self> {
-        if name.starts_with('.') {
+        if name.contains('.') {
             Err(Error::InvalidName)?
         }
+        if name == signers::ALLOWED_SIGNERS {
+            Err(Error::ReservedName(name.to_string()))?
+        }
 
         Ok(Self {
             name: name.to_string(),
             username: username.to_string(),
             email: email.to_string(),
+            remotes: vec![],
+            sign: false,
+            gpg_key: None,
+            key: None,
+            key_created: None,
+            rotated: vec![],
         })
     }
 
+    /// Path of profile's private ssh key, either the one referenced in place or `~/.ssh/id_<name>`
+    pub fn private_key(&self) -> String {
+        match &self.key {
+            Some(key) => key.to_string_lossy().to_string(),
+            None => ssh::key::path_private(&self.name),
+        }
+    }
+
+    /// Path of profile's public ssh key, next to the private one
+    pub fn public_key(&self) -> String {
+        ssh::key::path_public_of(&self.private_key())
+    }
+
+    /// Path of profile's own known_hosts file, `None` until a host is added to it.
+    /// Without it, ssh uses the user's known_hosts.
+    pub fn known_hosts(&self) -> Option<String> {
+        Some(known_hosts_path(&self.name)).filter(|path| Path::new(path).exists())
+    }
+
+    /// Whole days since g generated profile's current ssh key, `None` if it's unknown
+    pub fn key_age_days(&self) -> Option<u64> {
+        self.key_created.map(|created| now().saturating_sub(created) / SECONDS_PER_DAY)
+    }
+
+    /// Path of the certificate of profile's ssh key, next to the private key
+    pub fn certificate(&self) -> String {
+        ssh::key::path_certificate_of(&self.private_key())
+    }
+
+    /// Reads certificate of profile's ssh key, `None` if there is none or it can't be read
+    pub fn read_certificate(&self) -> Option<Certificate> {
+        ssh::cert::read(Path::new(&self.certificate())).ok()
+    }
+
+    /// Reads profile's public key, re-generating it from the private one and falling back to the public key file
+    pub fn read_public_key(&self) -> Option<PublicKey> {
+        ssh::key::public_from_private_file(Path::new(&self.private_key()), &self.email)
+            .or_else(|_| PublicKey::read_openssh_file(Path::new(&self.public_key())))
+            .ok()
+    }
+
     /// Reads and deserializes [`Profile`] from [`PROFILES_DIR`]
     ///
-    /// Doesn't return any specific errors, just forwards the ones related to io and deserialization
+    /// Profiles saved by older versions of g are migrated to the current format version.
+    /// If the profile is saved in a different [`Store`] than the one set in [`Settings`],
+    /// it's converted to the configured one.
+    /// Either way the file is re-written, so that the migration happens just once.
+    ///
+    /// [`Error::NotFound`] is returned if there's no such profile,
+    /// [`Error::UnsupportedVersion`] if it was saved by a newer version of g,
+    /// other errors are related to io and deserialization
     ///
     /// ```
     /// let name = "example";
     /// let profile = Profile::load(name).expect("Can't load profile '{name}'");
     /// ```
     pub fn load(profile_name: &str) -> Result<Self> {
-        let path = profile_path(profile_name);
-        let bytes = fs::read(&path)
-            .map_err(|e| Error::Io(e, path.into()))?;
-        let partial = bincode::deserialize(&bytes[..])?;
+        let store = Settings::load()?.store;
+        let (path, partial, outdated) = match stored_path(profile_name, store) {
+            Some((stored_in, path)) => {
+                let (partial, outdated) = migration::decode(stored_in, &read(&path)?)?;
+                (path, partial, outdated || stored_in != store)
+            }
+            None => {
+                let path = migration::json_path(profile_name);
+                if !Path::new(&path).exists() {
+                    Err(Error::NotFound(profile_name.to_string()))?
+                }
+                let partial = migration::decode_json(&read(&path)?)?;
+                (path, partial, true)
+            }
+        };
+        let profile = Self::from((profile_name, partial));
+        if outdated {
+            profile.clone().write(store)?;
+            if path != profile_path(profile_name, store) {
+                fs::remove_file(&path)
+                    .map_err(|e| Error::Io(e, path.into()))?;
+            }
+            // profiles of g 1.x aren't cached yet
+            cache::insert(&profile)?;
+        }
 
-        Ok((profile_name, partial).into())
+        Ok(profile)
     }
 
     /// Serializes and saves [`Profile`] to [`PROFILES_DIR`] and caches its name.
+    /// The profile is saved in [`Store`] set in [`Settings`].
     ///
     /// # Errors
     /// - [`Error::ProfileExists`] if profile with the same name is already saved to [`PROFILES_DIR`]
     /// - [`Error::CombinationExists`] if username/email combination is already in use by another profile
-    /// (either username or email can overlap, but not both at the same time)
+    ///   (either username or email can overlap, but not both at the same time)
     pub fn save(self, overwrite: bool) -> Result<()> {
-        let (profile_name, partial) = self.clone().into();
-        let path = profile_path(&profile_name);
-        if Path::new(&path).exists() && !overwrite {
-            Err(Error::ProfileExists(profile_name))?
+        let store = Settings::load()?.store;
+        let stored = stored_path(&self.name, store);
+        if stored.is_some() && !overwrite {
+            Err(Error::ProfileExists(self.name.to_string()))?
         }
-        let bytes = bincode::serialize(&partial)?;
+        if let Some(existing) = cache::get(&self.username, &self.email).filter(|existing| existing != &self.name) {
+            Err(Error::CombinationExists {
+                username: (self.username).to_string(),
+                email: (self.email).to_string(),
+                existing,
+            })?
+        }
+        if let Some((stored_in, path)) = stored {
+            if stored_in != store {
+                fs::remove_file(&path)
+                    .map_err(|e| Error::Io(e, path.into()))?;
+            }
+        }
+        self.clone().write(store)?;
+        cache::insert(&self)?;
+
+        Ok(())
+    }
+
+    fn write(self, store: Store) -> Result<()> {
+        let (profile_name, partial) = self.into();
+        let path = profile_path(&profile_name, store);
+        let bytes = migration::encode(store, &partial)?;
         fs::write(&path, &bytes[..])
             .map_err(|e| Error::Io(e, path.into()))?;
 
-        cache::get(&self.username, &self.username)
-            .map_or_else(
-                || cache::insert(&self),
-                |existing| {
-                    Err(Error::CombinationExists {
-                        username: (self.username).to_string(),
-                        email: (self.email).to_string(),
-                        existing,
-                    })
-                },
-            )?;
-
         Ok(())
     }
 }
 
+fn read(path: &str) -> Result<Vec<u8>> {
+    fs::read(path)
+        .map_err(|e| Error::Io(e, path.into()))
+}
+
 impl Display for Profile {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = &self.name;
         let user_name = &self.username;
         let user_email = &self.email;
+        let method = match &self.gpg_key {
+            Some(key) => format!("openpgp (key {key})"),
+            None => "ssh".to_string(),
+        };
+        let signing = if self.sign {
+            format!("{method}, commits and tags signed")
+        } else {
+            format!("{method}, on demand")
+        };
+        let remotes = if self.remotes.is_empty() {
+            String::new()
+        } else {
+            format!("\nremotes:        {}", self.remotes.join(", "))
+        };
 
         write!(f, r#"
 Profile '{name}'
 username:       {user_name}
 email:          {user_email}
-ssh key:        {}/.ssh/id_{name}
-        "#, home())
+ssh key:        {}
+signing:        {signing}{remotes}"#, self.private_key())
     }
 }
 
@@ -117,6 +275,12 @@ impl From<(&str, PartialProfile)> for Profile {
             name: String::from(name),
             username: partial.username,
             email: partial.email,
+            remotes: partial.remotes,
+            sign: partial.sign,
+            gpg_key: partial.gpg_key,
+            key: partial.key,
+            key_created: partial.key_created,
+            rotated: partial.rotated,
         }
     }
 }
@@ -126,8 +290,186 @@ impl From<Profile> for (String, PartialProfile) {
         let partial = PartialProfile {
             username: profile.username,
             email: profile.email,
+            remotes: profile.remotes,
+            sign: profile.sign,
+            gpg_key: profile.gpg_key,
+            key: profile.key,
+            key_created: profile.key_created,
+            rotated: profile.rotated,
         };
 
         (profile.name, partial)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::prelude::{PathAssertions, StrAssertions};
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    #[case::hidden(".hidden")]
+    #[case::toml("work.toml")]
+    #[case::json("work.json")]
+    #[case::known_hosts("work.known_hosts")]
+    #[case::dotted("john.smith")]
+    fn invalid_name(#[case] name: &str) {
+        let err = Profile::new(name, "Test Username", "test@email.com").unwrap_err();
+
+        assert_that!(err.to_string()).is_equal_to(Error::InvalidName.to_string());
+    }
+
+    #[test]
+    fn reserved_name() {
+        let err = Profile::new(signers::ALLOWED_SIGNERS, "Test Username", "test@email.com").unwrap_err();
+
+        assert_that!(err.to_string()).is_equal_to(Error::ReservedName(signers::ALLOWED_SIGNERS.to_string()).to_string());
+    }
+
+    #[rstest]
+    fn load_legacy(_fake_home: TempDir) {
+        let legacy = (String::from("Test Username"), String::from("test@email.com"));
+        fs::write(profile_path("legacy", Store::Bincode), bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let profile = Profile::load("legacy").unwrap();
+
+        assert_that!(profile).is_equal_to(Profile::new("legacy", "Test Username", "test@email.com").unwrap());
+    }
+
+    #[rstest]
+    fn load_unsigned(_fake_home: TempDir) {
+        let unsigned = (
+            String::from("Test Username"),
+            String::from("test@email.com"),
+            vec![String::from("git@github.com:acme/**")],
+        );
+        fs::write(profile_path("unsigned", Store::Bincode), bincode::serialize(&unsigned).unwrap()).unwrap();
+
+        let profile = Profile::load("unsigned").unwrap();
+
+        assert_that!(profile.remotes).is_equal_to(vec![String::from("git@github.com:acme/**")]);
+        assert_that!(profile.sign).is_equal_to(false);
+    }
+
+    #[rstest]
+    fn load_ssh_signing(_fake_home: TempDir) {
+        let ssh_signing = (String::from("Test Username"), String::from("test@email.com"), Vec::<String>::new(), true);
+        fs::write(profile_path("ssh_signing", Store::Bincode), bincode::serialize(&ssh_signing).unwrap()).unwrap();
+
+        let profile = Profile::load("ssh_signing").unwrap();
+
+        assert_that!(profile.sign).is_equal_to(true);
+        assert_that!(profile.gpg_key).is_equal_to(None);
+    }
+
+    #[rstest]
+    fn save_and_load(_fake_home: TempDir) {
+        let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+        profile.remotes = vec!["git@github.com:acme/**".to_string()];
+        profile.sign = true;
+        profile.gpg_key = Some("3AA5C34371567BD2".to_string());
+        profile.clone().save(false).unwrap();
+
+        assert_that!(Profile::load("test").unwrap()).is_equal_to(profile);
+        assert_that!(Path::new(&profile_path("test", Store::Toml))).exists();
+    }
+
+    #[rstest]
+    fn load_hand_written(_fake_home: TempDir) {
+        fs::write(
+            profile_path("test", Store::Toml),
+            "username = \"Test Username\"\nemail = \"test@email.com\"\n",
+        ).unwrap();
+
+        let profile = Profile::load("test").unwrap();
+
+        assert_that!(profile).is_equal_to(Profile::new("test", "Test Username", "test@email.com").unwrap());
+    }
+
+    #[rstest]
+    fn load_converts_to_toml(_fake_home: TempDir) {
+        let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+        profile.sign = true;
+        let (_, partial) = profile.clone().into();
+        fs::write(profile_path("test", Store::Bincode), bincode::serialize(&partial).unwrap()).unwrap();
+
+        assert_that!(Profile::load("test").unwrap()).is_equal_to(&profile);
+
+        assert_that!(Path::new(&profile_path("test", Store::Bincode))).does_not_exist();
+        let content = fs::read_to_string(profile_path("test", Store::Toml)).unwrap();
+        assert_that!(content.as_str()).contains("username = \"Test Username\"");
+        assert_that!(Profile::load("test").unwrap()).is_equal_to(profile);
+    }
+
+    #[rstest]
+    fn load_converts_to_bincode(_fake_home: TempDir) {
+        let profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+        profile.clone().save(false).unwrap();
+        Settings { store: Store::Bincode, ..Settings::default() }.save().unwrap();
+
+        assert_that!(Profile::load("test").unwrap()).is_equal_to(&profile);
+
+        assert_that!(Path::new(&profile_path("test", Store::Toml))).does_not_exist();
+        assert_that!(Path::new(&profile_path("test", Store::Bincode))).exists();
+        assert_that!(Profile::load("test").unwrap()).is_equal_to(profile);
+    }
+
+    #[rstest]
+    fn load_json(_fake_home: TempDir) {
+        let json = migration::json_path("json");
+        fs::write(&json, r#"{"username": "Test Username", "email": "test@email.com"}"#).unwrap();
+
+        let profile = Profile::load("json").unwrap();
+
+        assert_that!(profile).is_equal_to(Profile::new("json", "Test Username", "test@email.com").unwrap());
+        assert_that!(Path::new(&json)).does_not_exist();
+        assert_that!(cache::get("Test Username", "test@email.com")).is_equal_to(Some("json".to_string()));
+        assert_that!(fs::read_to_string(profile_path("json", Store::Toml)).unwrap().as_str())
+            .starts_with(format!("version = {}\n", migration::FORMAT_VERSION).as_str());
+    }
+
+    #[rstest]
+    fn load_rewrites_outdated(_fake_home: TempDir) {
+        Settings { store: Store::Bincode, ..Settings::default() }.save().unwrap();
+        let legacy = (String::from("Test Username"), String::from("test@email.com"));
+        fs::write(profile_path("legacy", Store::Bincode), bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let profile = Profile::load("legacy").unwrap();
+
+        let bytes = fs::read(profile_path("legacy", Store::Bincode)).unwrap();
+        assert_that!(bytes.starts_with(b"gprf")).is_equal_to(true);
+        assert_that!(Profile::load("legacy").unwrap()).is_equal_to(profile);
+    }
+
+    #[rstest]
+    #[case::unknown(None, None)]
+    #[case::today(Some(0), Some(0))]
+    #[case::days_ago(Some(3 * SECONDS_PER_DAY + 60), Some(3))]
+    fn key_age_days(#[case] age: Option<u64>, #[case] expected: Option<u64>) {
+        let mut profile = Profile::new("test", "Test Username", "test@email.com").unwrap();
+        profile.key_created = age.map(|age| now() - age);
+
+        assert_that!(profile.key_age_days()).is_equal_to(expected);
+    }
+
+    #[rstest]
+    fn load_missing(_fake_home: TempDir) {
+        assert_that!(Profile::load("missing").unwrap_err().to_string())
+            .is_equal_to("Profile with name 'missing' doesn't exist".to_string());
+    }
+}
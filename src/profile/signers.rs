@@ -0,0 +1,107 @@
+use std::fs;
+
+use crate::profile::error::Error;
+use crate::profile::model::Profile;
+use crate::profile::{list, profiles_dir, Result};
+
+pub(crate) const ALLOWED_SIGNERS: &str = "allowed_signers";
+
+/// Path of the allowed signers file, to be set as `gpg.ssh.allowedSignersFile` in git config
+pub(crate) fn path() -> String {
+    format!("{}/{ALLOWED_SIGNERS}", profiles_dir())
+}
+
+/// Re-writes the allowed signers file with email and public key of every profile,
+/// so that git can verify ssh signatures made with any of them.
+///
+/// Public keys are re-generated from private ones, falling back to public key files.
+/// Profiles without readable keys are skipped.
+///
+/// ```
+/// regenerate().expect("Can't write allowed signers");
+/// ```
+pub fn regenerate() -> Result<()> {
+    let lines = list()?
+        .into_iter()
+        .filter_map(|name| Profile::load(&name).ok())
+        .filter_map(|profile| signer(&profile))
+        .collect::<Vec<_>>();
+    let path = path();
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(&path, content)
+        .map_err(|e| Error::Io(e, path.into()))?;
+
+    Ok(())
+}
+
+fn signer(profile: &Profile) -> Option<String> {
+    let public = profile.read_public_key()?.to_openssh().ok()?;
+
+    Some(format!("{} namespaces=\"git\" {public}", profile.email))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::Path;
+
+    use rand::thread_rng;
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use ssh_key::{Algorithm, LineEnding, PrivateKey, PublicKey};
+    use tempfile::{tempdir, TempDir};
+
+    use crate::ssh;
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    fn regenerate_from_keys(_fake_home: TempDir) {
+        let with_private = Profile::new("private", "Private", "private@email.com").unwrap();
+        let with_public = Profile::new("public", "Public", "public@email.com").unwrap();
+        let without_keys = Profile::new("none", "None", "none@email.com").unwrap();
+        for profile in [&with_private, &with_public, &without_keys] {
+            profile.clone().save(false).unwrap();
+        }
+        let private = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+        private.write_openssh_file(Path::new(&ssh::key::path_private(&with_private.name)), LineEnding::LF)
+            .unwrap();
+        let mut public = PublicKey::from(&PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap());
+        public.set_comment(&with_public.email);
+        public.write_openssh_file(Path::new(&ssh::key::path_public(&with_public.name))).unwrap();
+
+        regenerate().unwrap();
+
+        let mut private_public = PublicKey::from(&private);
+        private_public.set_comment(&with_private.email);
+        let mut lines = fs::read_to_string(path()).unwrap()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        lines.sort();
+        assert_that!(lines).is_equal_to(vec![
+            format!("private@email.com namespaces=\"git\" {}", private_public.to_openssh().unwrap()),
+            format!("public@email.com namespaces=\"git\" {}", public.to_openssh().unwrap()),
+        ]);
+    }
+
+    #[rstest]
+    fn regenerate_empty(_fake_home: TempDir) {
+        regenerate().unwrap();
+
+        assert_that!(fs::read_to_string(path()).unwrap()).is_equal_to(String::new());
+    }
+}
@@ -0,0 +1,14 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0}, path: {1}")]
+    Io(#[source] io::Error, PathBuf),
+    #[error("Invalid settings: {0}")]
+    Deserialize(#[from] toml::de::Error),
+    #[error(transparent)]
+    Serialize(#[from] toml::ser::Error),
+}
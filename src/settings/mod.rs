@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::profile::profiles_dir;
+use crate::settings::error::Error;
+use crate::ssh::key::policy::Policy;
+
+pub mod error;
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Format profiles are stored in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Store {
+    /// Human-editable `<name>.toml` files
+    #[default]
+    Toml,
+    /// Binary `<name>` files, the format profiles used to be stored in
+    Bincode,
+}
+
+/// Settings of g, kept in `.settings.toml` next to profiles
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub store: Store,
+    /// Hosts to write `Host <host>-<profile>` aliases for into `~/.ssh/config.d/g`, none unless opted in
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub host_aliases: Vec<String>,
+    /// Template of `core.sshCommand` g sets for a profile, `{key}` is replaced with shell quoted path of profile's
+    /// private key and `{profile}` with its name. Defaults to [`crate::git::DEFAULT_SSH_COMMAND`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_command: Option<String>,
+    /// Rules for ssh keys, in the `[key_policy]` section
+    #[serde(skip_serializing_if = "Policy::is_default")]
+    pub key_policy: Policy,
+}
+
+impl Settings {
+    /// Loads settings, falling back to defaults if there's no settings file yet
+    ///
+    /// ```
+    /// let settings = Settings::load().expect("Can't load settings");
+    /// ```
+    pub fn load() -> Result<Self> {
+        let path = settings_path();
+        if !Path::new(&path).exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| Error::Io(e, path.into()))?;
+        let settings = toml::from_str(&content)?;
+
+        Ok(settings)
+    }
+
+    /// Saves settings, overwriting the existing ones
+    pub fn save(&self) -> Result<()> {
+        let path = settings_path();
+        let content = toml::to_string(self)?;
+        fs::write(&path, content)
+            .map_err(|e| Error::Io(e, path.into()))?;
+
+        Ok(())
+    }
+}
+
+fn settings_path() -> String {
+    format!("{}/.settings.toml", profiles_dir())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".config/g-profiles")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[rstest]
+    fn default_without_file(_fake_home: TempDir) {
+        assert_that!(Settings::load().unwrap()).is_equal_to(Settings::default());
+    }
+
+    #[rstest]
+    fn save_and_load(_fake_home: TempDir) {
+        let settings = Settings { store: Store::Bincode, ..Settings::default() };
+
+        settings.save().unwrap();
+
+        assert_that!(fs::read_to_string(settings_path()).unwrap()).is_equal_to("store = \"bincode\"\n".to_string());
+        assert_that!(Settings::load().unwrap()).is_equal_to(settings);
+    }
+
+    #[rstest]
+    fn key_policy(_fake_home: TempDir) {
+        fs::write(settings_path(), "[key_policy]\nmin_rsa_size = 4096\n").unwrap();
+
+        let settings = Settings::load().unwrap();
+
+        assert_that!(settings.store).is_equal_to(Store::Toml);
+        assert_that!(settings.key_policy).is_equal_to(Policy { min_rsa_size: 4096, ..Policy::default() });
+    }
+
+    #[rstest]
+    fn host_aliases(_fake_home: TempDir) {
+        let settings = Settings { host_aliases: vec!["github.com".to_string()], ..Settings::default() };
+
+        settings.save().unwrap();
+
+        assert_that!(fs::read_to_string(settings_path()).unwrap())
+            .is_equal_to("store = \"toml\"\nhost_aliases = [\"github.com\"]\n".to_string());
+        assert_that!(Settings::load().unwrap()).is_equal_to(settings);
+    }
+}
@@ -0,0 +1,223 @@
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use ssh_encoding::{Decode, Encode};
+use ssh_key::public::KeyData;
+use ssh_key::{PrivateKey, PublicKey};
+
+use crate::ssh::error::Error;
+use crate::ssh::Result;
+
+pub(crate) const AUTH_SOCK: &str = "SSH_AUTH_SOCK";
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_ADD_IDENTITY: u8 = 17;
+const SSH_AGENTC_REMOVE_IDENTITY: u8 = 18;
+
+/// Client of a running ssh-agent, speaking the agent protocol over its unix socket
+pub struct Agent {
+    socket: PathBuf,
+    stream: UnixStream,
+}
+
+impl Agent {
+    /// Connects to the agent `SSH_AUTH_SOCK` points at
+    ///
+    /// ```
+    /// let mut agent = Agent::connect().expect("Can't connect to ssh-agent");
+    /// ```
+    pub fn connect() -> Result<Self> {
+        let socket = env::var_os(AUTH_SOCK).ok_or(Error::AgentNotRunning)?;
+        Self::connect_to(Path::new(&socket))
+    }
+
+    /// Connects to the agent listening on `socket`
+    pub fn connect_to(socket: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(socket)
+            .map_err(|e| Error::Io(e, socket.to_path_buf()))?;
+
+        Ok(Self { socket: socket.to_path_buf(), stream })
+    }
+
+    /// Public keys of identities the agent holds, identities of types g doesn't know are skipped
+    pub fn identities(&mut self) -> Result<Vec<PublicKey>> {
+        let response = self.request(SSH_AGENTC_REQUEST_IDENTITIES, &[])?;
+        let mut reader = expect(SSH_AGENT_IDENTITIES_ANSWER, &response)?;
+        let count = u32::decode(&mut reader)?;
+        let mut identities = vec![];
+        for _ in 0..count {
+            let blob = Vec::<u8>::decode(&mut reader)?;
+            let comment = String::decode(&mut reader)?;
+            if let Ok(key_data) = KeyData::decode(&mut &blob[..]) {
+                identities.push(PublicKey::new(key_data, comment));
+            }
+        }
+
+        Ok(identities)
+    }
+
+    /// Adds `key` with `comment` to the agent, the key has to be decrypted
+    pub fn add(&mut self, key: &PrivateKey, comment: &str) -> Result<()> {
+        let mut payload = vec![];
+        key.key_data().encode(&mut payload)?;
+        comment.encode(&mut payload)?;
+        let response = self.request(SSH_AGENTC_ADD_IDENTITY, &payload)?;
+        expect(SSH_AGENT_SUCCESS, &response)?;
+
+        Ok(())
+    }
+
+    /// Removes identity with public `key` from the agent
+    pub fn remove(&mut self, key: &KeyData) -> Result<()> {
+        let mut payload = vec![];
+        key.encode_prefixed(&mut payload)?;
+        let response = self.request(SSH_AGENTC_REMOVE_IDENTITY, &payload)?;
+        expect(SSH_AGENT_SUCCESS, &response)?;
+
+        Ok(())
+    }
+
+    /// Sends a message of `kind` with `payload` and reads the response, whose first byte is its kind
+    fn request(&mut self, kind: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut message = vec![];
+        (payload.len() as u32 + 1).encode(&mut message)?;
+        message.push(kind);
+        message.extend_from_slice(payload);
+        self.stream.write_all(&message)
+            .map_err(|e| Error::Io(e, self.socket.clone()))?;
+
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)
+            .map_err(|e| Error::Io(e, self.socket.clone()))?;
+        let mut response = vec![0; u32::from_be_bytes(len) as usize];
+        self.stream.read_exact(&mut response)
+            .map_err(|e| Error::Io(e, self.socket.clone()))?;
+
+        Ok(response)
+    }
+}
+
+/// Checks that `response` is of `kind`, returning the rest of it
+fn expect(kind: u8, response: &[u8]) -> Result<&[u8]> {
+    match response.split_first() {
+        Some((&actual, rest)) if actual == kind => Ok(rest),
+        Some((&SSH_AGENT_FAILURE, _)) => Err(Error::AgentFailure),
+        Some((&actual, _)) => Err(Error::AgentResponse(actual)),
+        None => Err(Error::AgentResponse(0)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    use rand::thread_rng;
+    use spectral::assert_that;
+    use spectral::prelude::VecAssertions;
+    use ssh_key::Algorithm;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// Stand-in agent keeping identities as (key blob, comment), serving a single connection
+    fn fake_agent() -> (TempDir, PathBuf) {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut identities: Vec<(Vec<u8>, String)> = vec![];
+            loop {
+                let mut len = [0; 4];
+                if stream.read_exact(&mut len).is_err() {
+                    return;
+                }
+                let mut message = vec![0; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut message).unwrap();
+                let mut reader = &message[1..];
+                let mut response = vec![];
+                match message[0] {
+                    SSH_AGENTC_REQUEST_IDENTITIES => {
+                        response.push(SSH_AGENT_IDENTITIES_ANSWER);
+                        (identities.len() as u32).encode(&mut response).unwrap();
+                        for (blob, comment) in &identities {
+                            blob.encode(&mut response).unwrap();
+                            comment.encode(&mut response).unwrap();
+                        }
+                    }
+                    SSH_AGENTC_ADD_IDENTITY => {
+                        let private = ssh_key::private::KeypairData::decode(&mut reader).unwrap();
+                        let comment = String::decode(&mut reader).unwrap();
+                        let mut blob = vec![];
+                        KeyData::try_from(&private).unwrap().encode(&mut blob).unwrap();
+                        identities.push((blob, comment));
+                        response.push(SSH_AGENT_SUCCESS);
+                    }
+                    SSH_AGENTC_REMOVE_IDENTITY => {
+                        let blob = Vec::<u8>::decode(&mut reader).unwrap();
+                        let count = identities.len();
+                        identities.retain(|(b, _)| *b != blob);
+                        response.push(if identities.len() < count { SSH_AGENT_SUCCESS } else { SSH_AGENT_FAILURE });
+                    }
+                    _ => response.push(SSH_AGENT_FAILURE),
+                }
+                let mut framed = vec![];
+                response.encode(&mut framed).unwrap();
+                stream.write_all(&framed).unwrap();
+            }
+        });
+
+        (dir, socket)
+    }
+
+    fn key(comment: &str) -> PrivateKey {
+        let mut key = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+        key.set_comment(comment);
+        key
+    }
+
+    #[test]
+    fn add_and_list() {
+        let (_dir, socket) = fake_agent();
+        let mut agent = Agent::connect_to(&socket).unwrap();
+        let key = key("work");
+
+        agent.add(&key, "/home/user/.ssh/id_work").unwrap();
+
+        let identities = agent.identities().unwrap();
+        assert_that!(identities).has_length(1);
+        assert_that!(identities[0].key_data()).is_equal_to(key.public_key().key_data());
+        assert_that!(identities[0].comment()).is_equal_to("/home/user/.ssh/id_work");
+    }
+
+    #[test]
+    fn remove() {
+        let (_dir, socket) = fake_agent();
+        let mut agent = Agent::connect_to(&socket).unwrap();
+        let (work, oss) = (key("work"), key("oss"));
+        agent.add(&work, "work").unwrap();
+        agent.add(&oss, "oss").unwrap();
+
+        agent.remove(work.public_key().key_data()).unwrap();
+
+        let identities = agent.identities().unwrap();
+        assert_that!(identities).has_length(1);
+        assert_that!(identities[0].key_data()).is_equal_to(oss.public_key().key_data());
+    }
+
+    #[test]
+    fn failure() {
+        let (_dir, socket) = fake_agent();
+        let mut agent = Agent::connect_to(&socket).unwrap();
+
+        let err = agent.remove(key("missing").public_key().key_data()).unwrap_err();
+
+        assert_that!(err.to_string()).is_equal_to(Error::AgentFailure.to_string());
+    }
+}
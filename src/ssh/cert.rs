@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::Path;
+
+use rand::thread_rng;
+use ssh_key::certificate::{Builder, CertType};
+use ssh_key::{Certificate, PrivateKey, PublicKey};
+
+use crate::ssh::error::Error;
+use crate::ssh::Result;
+
+/// Extensions ssh-keygen grants user certificates unless told otherwise
+const DEFAULT_EXTENSIONS: [&str; 5] = [
+    "permit-X11-forwarding",
+    "permit-agent-forwarding",
+    "permit-port-forwarding",
+    "permit-pty",
+    "permit-user-rc",
+];
+/// Certificates become valid a bit before they're issued, so that a server whose clock is behind accepts them
+const BACKDATE: u64 = 5 * 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What a user certificate is issued for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Request {
+    /// Identifies the certificate in server logs, e.g. email of the profile
+    pub key_id: String,
+    /// User names the certificate is valid for, it can't be issued without any
+    pub principals: Vec<String>,
+    pub serial: u64,
+    /// Unix timestamp the certificate is issued at
+    pub issued: u64,
+    /// Seconds the certificate is valid for since it's issued
+    pub validity: u64,
+}
+
+/// Issues an OpenSSH user certificate for `public` key signed by `ca` key, which has to be decrypted
+///
+/// ```
+/// let request = Request {
+///     key_id: "user@example.com".to_string(), principals: vec!["user".to_string()], serial: 0, issued: now(), validity: 86400,
+/// };
+/// let certificate = sign(&public, &ca, &request).expect("Can't issue certificate");
+/// ```
+pub(crate) fn sign(public: &PublicKey, ca: &PrivateKey, request: &Request) -> Result<Certificate> {
+    if request.principals.is_empty() {
+        Err(Error::NoPrincipals)?
+    }
+    let valid_after = request.issued.saturating_sub(BACKDATE);
+    let valid_before = request.issued.saturating_add(request.validity);
+    let mut builder = Builder::new_with_random_nonce(
+        &mut thread_rng(), public.key_data().clone(), valid_after, valid_before,
+    )?;
+    builder
+        .cert_type(CertType::User)?
+        .serial(request.serial)?
+        .key_id(&request.key_id)?
+        .comment(public.comment())?;
+    for principal in &request.principals {
+        builder.valid_principal(principal)?;
+    }
+    for extension in DEFAULT_EXTENSIONS {
+        builder.extension(extension, "")?;
+    }
+
+    Ok(builder.sign(ca)?)
+}
+
+/// Reads OpenSSH certificate from `path`
+pub(crate) fn read(path: &Path) -> Result<Certificate> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| Error::Io(e, path.to_path_buf()))?;
+
+    Ok(Certificate::from_openssh(&content)?)
+}
+
+/// Writes `certificate` in OpenSSH format into `path`
+pub(crate) fn write(path: &Path, certificate: &Certificate) -> Result<()> {
+    fs::write(path, format!("{}\n", certificate.to_openssh()?))
+        .map_err(|e| Error::Io(e, path.to_path_buf()))
+}
+
+/// Describes principals of `certificate` and when it expires as of `now` (unix timestamp),
+/// e.g. `principals git, johnsmith, expires in 29 day(s)`
+pub(crate) fn describe(certificate: &Certificate, now: u64) -> String {
+    let expiry = certificate.valid_before();
+    let expiry = if expiry > now {
+        format!("expires in {} day(s)", (expiry - now) / SECONDS_PER_DAY)
+    } else {
+        format!("expired {} day(s) ago", (now - expiry) / SECONDS_PER_DAY)
+    };
+
+    format!("principals {}, {expiry}", certificate.valid_principals().join(", "))
+}
+
+/// Parses validity period the way `ssh-keygen -V` takes relative ones, e.g. `+52w`, `1d12h` or `3600`.
+/// Units are `s`, `m`, `h`, `d` and `w`, a number without unit is in seconds.
+///
+/// Returns the period in seconds.
+pub(crate) fn parse_validity(validity: &str) -> Result<u64> {
+    let invalid = || Error::InvalidValidity(validity.to_string());
+    let period = validity.strip_prefix('+').unwrap_or(validity);
+    if period.is_empty() {
+        Err(invalid())?
+    }
+    let mut seconds = 0u64;
+    let mut number = String::new();
+    for c in period.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => Err(invalid())?,
+        };
+        let count = number.parse::<u64>().map_err(|_| invalid())?;
+        seconds = count.checked_mul(unit).and_then(|s| seconds.checked_add(s)).ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        let count = number.parse::<u64>().map_err(|_| invalid())?;
+        seconds = seconds.checked_add(count).ok_or_else(invalid)?;
+    }
+    if seconds == 0 {
+        Err(invalid())?
+    }
+
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use spectral::assert_that;
+    use spectral::prelude::BooleanAssertions;
+    use ssh_key::{Algorithm, HashAlg};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    const ISSUED: u64 = 1_700_000_000;
+
+    fn request(principals: &[&str]) -> Request {
+        Request {
+            key_id: "test@email.com".to_string(),
+            principals: principals.iter().map(|p| p.to_string()).collect(),
+            serial: 42,
+            issued: ISSUED,
+            validity: 7 * 24 * 60 * 60,
+        }
+    }
+
+    fn key(comment: &str) -> PrivateKey {
+        let mut key = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+        key.set_comment(comment);
+        key
+    }
+
+    #[test]
+    fn sign_user_certificate() {
+        let (user, ca) = (key("test@email.com"), key("ca"));
+
+        let certificate = sign(user.public_key(), &ca, &request(&["git", "test"])).unwrap();
+
+        assert_that!(certificate.cert_type()).is_equal_to(CertType::User);
+        assert_that!(certificate.public_key()).is_equal_to(user.public_key().key_data());
+        assert_that!(certificate.key_id()).is_equal_to("test@email.com");
+        assert_that!(certificate.serial()).is_equal_to(42);
+        assert_that!(certificate.comment()).is_equal_to("test@email.com");
+        assert_that!(certificate.valid_principals().to_vec()).is_equal_to(vec!["git".to_string(), "test".to_string()]);
+        assert_that!(certificate.valid_after()).is_equal_to(ISSUED - BACKDATE);
+        assert_that!(certificate.valid_before()).is_equal_to(ISSUED + 7 * 24 * 60 * 60);
+        assert_that!(certificate.extensions().contains_key("permit-pty")).is_true();
+        let ca_fingerprint = ca.public_key().fingerprint(HashAlg::Sha256);
+        assert_that!(certificate.validate_at(ISSUED, [&ca_fingerprint]).is_ok()).is_true();
+    }
+
+    #[test]
+    fn sign_without_principals() {
+        let err = sign(key("user").public_key(), &key("ca"), &request(&[])).unwrap_err();
+
+        assert_that!(err.to_string()).is_equal_to(Error::NoPrincipals.to_string());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("id_test-cert.pub");
+        let certificate = sign(key("user").public_key(), &key("ca"), &request(&["git"])).unwrap();
+
+        write(&path, &certificate).unwrap();
+
+        assert_that!(read(&path).unwrap()).is_equal_to(certificate);
+    }
+
+    #[rstest]
+    #[case::valid(ISSUED + SECONDS_PER_DAY, "principals git, test, expires in 6 day(s)")]
+    #[case::expired(ISSUED + 9 * SECONDS_PER_DAY, "principals git, test, expired 2 day(s) ago")]
+    fn describe_expiry(#[case] now: u64, #[case] expected: &str) {
+        let certificate = sign(key("user").public_key(), &key("ca"), &request(&["git", "test"])).unwrap();
+
+        assert_that!(describe(&certificate, now)).is_equal_to(expected.to_string());
+    }
+
+    #[rstest]
+    #[case::seconds("3600", 3600)]
+    #[case::plus_weeks("+52w", 52 * 7 * 24 * 60 * 60)]
+    #[case::combined("1d12h", 36 * 60 * 60)]
+    #[case::trailing_seconds("1m30", 90)]
+    #[case::upper_case("2H", 2 * 60 * 60)]
+    fn relative_validity(#[case] validity: &str, #[case] expected: u64) {
+        assert_that!(parse_validity(validity).unwrap()).is_equal_to(expected);
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::plus("+")]
+    #[case::zero("0d")]
+    #[case::unit_only("d")]
+    #[case::unknown_unit("1y")]
+    #[case::absolute("20240101:20250101")]
+    fn invalid_validity(#[case] validity: &str) {
+        assert_that!(parse_validity(validity).unwrap_err().to_string())
+            .is_equal_to(Error::InvalidValidity(validity.to_string()).to_string());
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ssh::error::Error;
+use crate::ssh::key::ssh_dir;
+use crate::ssh::Result;
+
+/// Path of the file g manages, relative to `~/.ssh` - the way ssh resolves `Include` in user config
+pub(crate) const INCLUDE: &str = "config.d/g";
+
+const HEADER: &str = "# Managed by g, changes will be overwritten";
+
+/// Path of the ssh config file g writes host aliases of profiles into
+pub(crate) fn path() -> PathBuf {
+    Path::new(&ssh_dir()).join(INCLUDE)
+}
+
+/// Name of the `Host` block for `host` that uses ssh key of profile `profile_name`, e.g. `github.com-work`
+pub(crate) fn alias(host: &str, profile_name: &str) -> String {
+    format!("{host}-{profile_name}")
+}
+
+/// Profile to write host aliases for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Identity {
+    pub name: String,
+    pub private_key: String,
+    /// Profile's own known_hosts file, if it has one
+    pub known_hosts: Option<String>,
+}
+
+/// Renders a `Host <host>-<name>` block for every host and every identity,
+/// which connects to `host` offering only identity's key
+pub(crate) fn render(hosts: &[String], identities: &[Identity]) -> String {
+    let mut content = format!("{HEADER}\n");
+    for host in hosts {
+        for identity in identities {
+            content.push_str(&format!(
+                "\nHost {}\n    HostName {host}\n    IdentityFile \"{}\"\n    IdentitiesOnly yes\n",
+                alias(host, &identity.name), identity.private_key
+            ));
+            if let Some(known_hosts) = &identity.known_hosts {
+                content.push_str(&format!("    UserKnownHostsFile \"{known_hosts}\"\n"));
+            }
+        }
+    }
+
+    content
+}
+
+/// Adds `Include config.d/g` at the top of `~/.ssh/config`, creating the config if needed.
+/// It has to come before any `Host` block, otherwise it would apply only to that block.
+///
+/// Returns `false` if the config already included the file.
+///
+/// ```
+/// if include().expect("Can't edit ssh config") {
+///     println!("ssh config includes {INCLUDE} now");
+/// }
+/// ```
+pub(crate) fn include() -> Result<bool> {
+    let path = user_config_path();
+    let content = read_user_config(&path)?;
+    if content.lines().any(is_include) {
+        return Ok(false);
+    }
+    fs::write(&path, format!("Include {INCLUDE}\n{content}"))
+        .map_err(|e| Error::Io(e, path))?;
+
+    Ok(true)
+}
+
+/// Removes the `Include config.d/g` line from `~/.ssh/config`, returns `false` if there was none
+pub(crate) fn exclude() -> Result<bool> {
+    let path = user_config_path();
+    let content = read_user_config(&path)?;
+    if !content.lines().any(is_include) {
+        return Ok(false);
+    }
+    let content = content
+        .lines()
+        .filter(|line| !is_include(line))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    fs::write(&path, content)
+        .map_err(|e| Error::Io(e, path))?;
+
+    Ok(true)
+}
+
+fn is_include(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    words.next().is_some_and(|keyword| keyword.eq_ignore_ascii_case("include"))
+        && words.any(|file| file == INCLUDE || file == format!("~/.ssh/{INCLUDE}"))
+}
+
+fn user_config_path() -> PathBuf {
+    Path::new(&ssh_dir()).join("config")
+}
+
+fn read_user_config(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    fs::read_to_string(path).map_err(|e| Error::Io(e, path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use rstest::{fixture, rstest};
+    use spectral::assert_that;
+    use spectral::prelude::BooleanAssertions;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        fs::create_dir_all(fake_home.path().join(".ssh")).unwrap();
+        env::set_var("HOME", fake_home.path().to_string_lossy().to_string());
+
+        fake_home
+    }
+
+    #[test]
+    fn render_blocks() {
+        let hosts = vec!["github.com".to_string(), "gitlab.com".to_string()];
+        let identities = vec![
+            Identity { name: "work".to_string(), private_key: "/home/user/.ssh/id_work".to_string(), known_hosts: None },
+            Identity {
+                name: "oss".to_string(),
+                private_key: "/home/user/.ssh/id_oss".to_string(),
+                known_hosts: Some("/home/user/.config/g-profiles/oss.known_hosts".to_string()),
+            },
+        ];
+
+        let content = render(&hosts, &identities);
+
+        assert_that!(content).is_equal_to(format!(
+            "{HEADER}\n\
+            \nHost github.com-work\n    HostName github.com\n    IdentityFile \"/home/user/.ssh/id_work\"\n    IdentitiesOnly yes\n\
+            \nHost github.com-oss\n    HostName github.com\n    IdentityFile \"/home/user/.ssh/id_oss\"\n    IdentitiesOnly yes\n\
+            \x20   UserKnownHostsFile \"/home/user/.config/g-profiles/oss.known_hosts\"\n\
+            \nHost gitlab.com-work\n    HostName gitlab.com\n    IdentityFile \"/home/user/.ssh/id_work\"\n    IdentitiesOnly yes\n\
+            \nHost gitlab.com-oss\n    HostName gitlab.com\n    IdentityFile \"/home/user/.ssh/id_oss\"\n    IdentitiesOnly yes\n\
+            \x20   UserKnownHostsFile \"/home/user/.config/g-profiles/oss.known_hosts\"\n"
+        ));
+    }
+
+    #[rstest]
+    fn include_prepends(fake_home: TempDir) {
+        let config = fake_home.path().join(".ssh/config");
+        fs::write(&config, "Host example.com\n    User git\n").unwrap();
+
+        assert_that!(include().unwrap()).is_true();
+        assert_that!(include().unwrap()).is_false();
+
+        assert_that!(fs::read_to_string(&config).unwrap())
+            .is_equal_to(format!("Include {INCLUDE}\nHost example.com\n    User git\n"));
+    }
+
+    #[rstest]
+    fn include_creates_config(fake_home: TempDir) {
+        include().unwrap();
+
+        assert_that!(fs::read_to_string(fake_home.path().join(".ssh/config")).unwrap())
+            .is_equal_to(format!("Include {INCLUDE}\n"));
+    }
+
+    #[rstest]
+    fn exclude_removes_include(fake_home: TempDir) {
+        let config = fake_home.path().join(".ssh/config");
+        fs::write(&config, "include ~/.ssh/config.d/g\nHost example.com\n    User git\n").unwrap();
+
+        assert_that!(exclude().unwrap()).is_true();
+        assert_that!(exclude().unwrap()).is_false();
+
+        assert_that!(fs::read_to_string(&config).unwrap()).is_equal_to("Host example.com\n    User git\n".to_string());
+    }
+}
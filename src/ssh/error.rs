@@ -1,15 +1,42 @@
+use std::io;
+use std::path::PathBuf;
+
 use thiserror::Error;
 
+use crate::ssh::key::policy::Violation;
 use crate::ssh::key::MIN_RSA_SIZE;
 
 #[derive(Error, Debug)]
-pub(crate) enum Error {
+pub enum Error {
     #[error("Unknown ssh key type: {0}")]
     UnknownKeyType(String),
     #[error("Key pair already exists")]
     KeyPairExists,
     #[error("Invalid RSA key length ({0}). Minimum is {MIN_RSA_SIZE} bits")]
     InvalidRsaLength(usize),
+    #[error("{0}")]
+    PolicyViolation(Violation),
+    #[error("Ssh keys of {0} profile(s) violate the key policy")]
+    PolicyViolations(usize),
+    #[error("Invalid validity period '{0}', expected e.g. +52w, 30d or 1d12h")]
+    InvalidValidity(String),
+    #[error("Certificate has to be valid for at least one principal")]
+    NoPrincipals,
+    #[error("No known_hosts entry for {0}")]
+    UnknownHost(String),
+    #[error("{0}, path: {1}")]
+    Io(#[source] io::Error, PathBuf),
+    #[cfg(unix)]
+    #[error("No ssh-agent is running, {} isn't set", crate::ssh::agent::AUTH_SOCK)]
+    AgentNotRunning,
+    #[cfg(unix)]
+    #[error("ssh-agent refused the request")]
+    AgentFailure,
+    #[cfg(unix)]
+    #[error("Unexpected response from ssh-agent (message type {0})")]
+    AgentResponse(u8),
+    #[error(transparent)]
+    Encoding(#[from] ssh_encoding::Error),
     #[error(transparent)]
     LibSsh2(#[from] ssh_key::Error),
 }
\ No newline at end of file
@@ -1,22 +1,30 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use rand::thread_rng;
-use ssh_key::private::{DsaKeypair, Ed25519Keypair, RsaKeypair};
+use ssh_key::private::{DsaKeypair, EcdsaKeypair, Ed25519Keypair, RsaKeypair};
 use ssh_key::{LineEnding, PrivateKey, PublicKey};
 
 use crate::home;
 use crate::ssh::error::Error;
+use crate::ssh::key::policy::Policy;
 use crate::ssh::key::r#type::KeyType;
-use crate::ssh::Result;
+use crate::ssh::{write_through_temporary, Result};
 
+pub mod policy;
 pub(crate) mod r#type;
 pub(super) const DEFAULT_RSA_SIZE: usize = 3072;
 pub(super) const MIN_RSA_SIZE: usize = 2048;
+/// Suffix of keys moved out of the way by [`retire`]
+pub(crate) const RETIRED_SUFFIX: &str = ".old";
+/// Suffix ssh expects certificate of a key to have, before `.pub`
+pub(crate) const CERTIFICATE_SUFFIX: &str = "-cert";
 
 // it's criminal these don't already have a common interface in the lib
 enum KeyPair {
     Dsa(DsaKeypair),
     Rsa(RsaKeypair),
+    Ecdsa(EcdsaKeypair),
     Ed25519(Ed25519Keypair),
 }
 
@@ -25,6 +33,7 @@ impl From<KeyPair> for PrivateKey {
         match pair {
             KeyPair::Dsa(pair) => PrivateKey::from(pair),
             KeyPair::Rsa(pair) => PrivateKey::from(pair),
+            KeyPair::Ecdsa(pair) => PrivateKey::from(pair),
             KeyPair::Ed25519(pair) => PrivateKey::from(pair),
         }
     }
@@ -32,26 +41,29 @@ impl From<KeyPair> for PrivateKey {
 
 /// Generate a pair of ssh keys with specified type
 ///
-/// `email` param is set as comment in the public key.
+/// `email` param is set as comment of the keys, the same way `ssh-keygen -C` does it.
 ///
-/// The only error specific to this function is when `key_type` is [`KeyType::Rsa`]
-/// with size less that [`MIN_RSA_SIZE`], other errors are forwarded from underlying [`ssh_key`] lib.
-pub fn pair(email: &str, key_type: &KeyType) -> Result<(PrivateKey, PublicKey)> {
+/// Errors specific to this function are when `key_type` is [`KeyType::Rsa`] with size less that [`MIN_RSA_SIZE`]
+/// and when `key_type` isn't allowed by `policy`, other errors are forwarded from underlying [`ssh_key`] lib.
+pub fn pair(email: &str, key_type: &KeyType, policy: &Policy) -> Result<(PrivateKey, PublicKey)> {
+    if let KeyType::Rsa { size: Some(size) } = key_type {
+        if *size < MIN_RSA_SIZE {
+            Err(Error::InvalidRsaLength(*size))?
+        }
+    }
+    policy.check(key_type)?;
     let mut rng = thread_rng();
     let pair = match key_type {
         KeyType::Dsa => KeyPair::Dsa(DsaKeypair::random(&mut rng)?),
-        KeyType::Rsa { size } => {
-            let size = size.unwrap_or(DEFAULT_RSA_SIZE);
-            if size < MIN_RSA_SIZE {
-                Err(Error::InvalidRsaLength(size))?
-            }
-            KeyPair::Rsa(RsaKeypair::random(&mut rng, size)?)
-        }
+        KeyType::Rsa { size } => KeyPair::Rsa(RsaKeypair::random(&mut rng, size.unwrap_or(DEFAULT_RSA_SIZE))?),
+        KeyType::Ecdsa { curve } => KeyPair::Ecdsa(EcdsaKeypair::random(&mut rng, *curve)?),
         KeyType::Ed25519 => KeyPair::Ed25519(Ed25519Keypair::random(&mut rng)),
     };
-    let private = PrivateKey::from(pair);
-    let mut public = PublicKey::from(&private);
-    public.set_comment(email);
+    let mut private = PrivateKey::from(pair);
+    // the comment isn't just cosmetic: base64ct (behind ssh-key's PEM decoder) fails a read of zero bytes at the end
+    // of input, which is what an empty comment is when the key needs no padding, e.g. most P-256 and P-384 keys
+    private.set_comment(email);
+    let public = PublicKey::from(&private);
 
     Ok((private, public))
 }
@@ -60,8 +72,15 @@ pub fn pair(email: &str, key_type: &KeyType) -> Result<(PrivateKey, PublicKey)>
 ///
 /// `email` param is set as comment in the public key.
 pub fn public_from_private(profile_name: &str, email: &str) -> Result<PublicKey> {
-    let private_key_path = path_private(profile_name);
-    PrivateKey::read_openssh_file(Path::new(&private_key_path))
+    public_from_private_file(Path::new(&path_private(profile_name)), email)
+}
+
+/// Re-generate public key from private key at `path`.
+/// OpenSSH keeps the public part of a private key unencrypted, so this works without passphrase for encrypted keys.
+///
+/// `email` param is set as comment in the public key.
+pub fn public_from_private_file(path: &Path, email: &str) -> Result<PublicKey> {
+    PrivateKey::read_openssh_file(path)
         .map(|private| {
             let mut public = PublicKey::from(&private);
             public.set_comment(email);
@@ -70,17 +89,147 @@ pub fn public_from_private(profile_name: &str, email: &str) -> Result<PublicKey>
         .map_err(|e| e.into())
 }
 
-/// Write private ssh key in openssh format into `~/.ssh/id_{profile_name}`
-pub fn write_private(profile_name: &str, key: &PrivateKey) -> Result<()> {
-    let key_path = path_private(profile_name);
-    key.write_openssh_file(Path::new(&key_path), LineEnding::LF)
+/// Encrypt private key with `passphrase`, the same way `ssh-keygen` does it (bcrypt-pbkdf and aes256-ctr).
+///
+/// ```
+/// let (private, _) = pair("user@example.com", &KeyType::Ed25519, &Policy::default()).unwrap();
+/// let encrypted = encrypt(&private, "correct horse battery staple").unwrap();
+/// ```
+pub fn encrypt(key: &PrivateKey, passphrase: &str) -> Result<PrivateKey> {
+    key.encrypt(&mut thread_rng(), passphrase)
+        .map_err(|e| e.into())
+}
+
+/// Decrypt private key encrypted with `passphrase`
+pub fn decrypt(key: &PrivateKey, passphrase: &str) -> Result<PrivateKey> {
+    key.decrypt(passphrase)
+        .map_err(|e| e.into())
+}
+
+/// Read OpenSSH private key from `path`, encrypted keys are returned as they are
+pub fn read_private_file(path: &Path) -> Result<PrivateKey> {
+    PrivateKey::read_openssh_file(path)
+        .map_err(|e| e.into())
+}
+
+/// Import existing OpenSSH private key from `path` as key of profile with specified name.
+/// The key is copied into `~/.ssh/id_{profile_name}` as is (so an encrypted key stays encrypted),
+/// the public key is derived from it with `email` as comment.
+/// Both keys are written through temporary files, so if either can't be written, the profile's keys stay as they were.
+///
+/// Unless `force` is set, [`Error::KeyPairExists`] is returned if profile already has any of the keys.
+/// Other errors come from reading or writing the keys, a file that isn't an OpenSSH private key is rejected.
+///
+/// ```
+/// let private = import("example", "user@example.com", Path::new("/home/user/.ssh/id_ed25519"), false).unwrap();
+/// ```
+pub fn import(profile_name: &str, email: &str, path: &Path, force: bool) -> Result<PrivateKey> {
+    let private_path = path_private(profile_name);
+    let public_path = path_public(profile_name);
+    if !force && (Path::new(&private_path).exists() || Path::new(&public_path).exists()) {
+        Err(Error::KeyPairExists)?
+    }
+    let private = PrivateKey::read_openssh_file(path)?;
+    let same_file = path.canonicalize().ok()
+        .is_some_and(|path| Path::new(&private_path).canonicalize().is_ok_and(|p| p == path));
+    let mut public = private.public_key().clone();
+    public.set_comment(email);
+    let write_public_key = |temporary: &Path| write_public_file(temporary, &public);
+    if same_file {
+        write_through_temporary(Path::new(&public_path), write_public_key)?;
+    } else {
+        write_through_temporary(Path::new(&private_path), |temporary| {
+            fs::copy(path, temporary)
+                .map_err(|e| Error::Io(e, path.to_path_buf()))?;
+            restrict_permissions(temporary)?;
+            // the private key takes its place only once the public key is in place
+            write_through_temporary(Path::new(&public_path), write_public_key)
+        })?;
+    }
+
+    Ok(private)
+}
+
+/// Adopt existing OpenSSH private key at `path` without moving it, so that a profile can reference it in place.
+/// If there's no public key next to it (`<path>.pub`), it's derived from the private one with `email` as comment.
+///
+/// Returns canonicalized `path` and the private key, a file that isn't an OpenSSH private key is rejected.
+///
+/// ```
+/// let (path, private) = adopt(Path::new("/home/user/.ssh/keys/work"), "user@example.com").unwrap();
+/// ```
+pub fn adopt(path: &Path, email: &str) -> Result<(PathBuf, PrivateKey)> {
+    let path = path.canonicalize()
+        .map_err(|e| Error::Io(e, path.to_path_buf()))?;
+    let private = PrivateKey::read_openssh_file(&path)?;
+    let public_path = path_public_of(&path.to_string_lossy());
+    if !Path::new(&public_path).exists() {
+        let mut public = private.public_key().clone();
+        public.set_comment(email);
+        write_public_file(Path::new(&public_path), &public)?;
+    }
+
+    Ok((path, private))
+}
+
+/// Makes file at `path` readable and writable only by its owner, the way ssh expects private keys to be
+#[cfg(unix)]
+pub(crate) fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| Error::Io(e, path.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Keep the key pair with private key at `private_path` as `<private_path>.<timestamp>.old` (and `.old.pub`),
+/// so that a new one can take its place while the old one is still registered with remotes.
+/// Certificate of the key pair is kept along as `.old-cert.pub`.
+///
+/// Returns the path the private key was moved to, `None` if there was no private key.
+///
+/// ```
+/// let retired = retire(Path::new("/home/user/.ssh/id_example"), 1700000000).unwrap();
+/// ```
+pub fn retire(private_path: &Path, timestamp: u64) -> Result<Option<PathBuf>> {
+    let private = private_path.to_string_lossy();
+    let retired = path_retired(&private, timestamp);
+    for (from, to) in [
+        (path_public_of(&private), path_public_of(&retired)),
+        (path_certificate_of(&private), path_certificate_of(&retired)),
+    ] {
+        if Path::new(&from).exists() {
+            fs::rename(&from, to)
+                .map_err(|e| Error::Io(e, from.into()))?;
+        }
+    }
+    if !private_path.exists() {
+        return Ok(None);
+    }
+    fs::rename(private_path, &retired)
+        .map_err(|e| Error::Io(e, private_path.to_path_buf()))?;
+
+    Ok(Some(retired.into()))
+}
+
+/// Write private ssh key in openssh format into `path`
+pub fn write_private_file(path: &Path, key: &PrivateKey) -> Result<()> {
+    key.write_openssh_file(path, LineEnding::LF)
         .map_err(|e| e.into())
 }
 
 /// Write public key in openssh format into `~/.ssh/id_{profile_name}.pub`
 pub fn write_public(profile_name: &str, key: &PublicKey) -> Result<()> {
-    let key_path = path_public(profile_name);
-    key.write_openssh_file(Path::new(&key_path))
+    write_public_file(Path::new(&path_public(profile_name)), key)
+}
+
+/// Write public key in openssh format into `path`
+pub fn write_public_file(path: &Path, key: &PublicKey) -> Result<()> {
+    key.write_openssh_file(path)
         .map_err(|e| e.into())
 }
 
@@ -89,10 +238,25 @@ pub(crate) fn path_private(profile_name: &str) -> String {
 }
 
 pub(crate) fn path_public(profile_name: &str) -> String {
-    format!("{}/id_{profile_name}.pub", ssh_dir())
+    path_public_of(&path_private(profile_name))
+}
+
+/// Path of the public key belonging to private key at `private_path`
+pub(crate) fn path_public_of(private_path: &str) -> String {
+    format!("{private_path}.pub")
+}
+
+/// Path private key at `private_path` is kept at by [`retire`] at `timestamp`
+pub(crate) fn path_retired(private_path: &str, timestamp: u64) -> String {
+    format!("{private_path}.{timestamp}{RETIRED_SUFFIX}")
 }
 
-fn ssh_dir() -> String {
+/// Path of the certificate belonging to private key at `private_path`, where ssh looks for it
+pub(crate) fn path_certificate_of(private_path: &str) -> String {
+    format!("{private_path}{CERTIFICATE_SUFFIX}.pub")
+}
+
+pub(crate) fn ssh_dir() -> String {
     format!("{}/.ssh", home())
 }
 
@@ -100,28 +264,43 @@ fn ssh_dir() -> String {
 mod test {
     use crate::ssh;
     use crate::ssh::error::Error;
+    use crate::ssh::key::policy::{Policy, Violation};
     use crate::ssh::key::r#type::KeyType;
     use rand::thread_rng;
-    use rstest::rstest;
+    use rstest::{fixture, rstest};
     use spectral::assert_that;
     use spectral::prelude::PathAssertions;
-    use ssh_key::{Algorithm, PrivateKey, PublicKey};
+    use ssh_key::{Algorithm, EcdsaCurve, PrivateKey, PublicKey};
     use std::{env, fs};
-    use tempfile::tempdir;
+    use tempfile::{tempdir, TempDir};
 
     const PROFILE_NAME: &str = "test";
     const EMAIL: &str = "test@email.com";
 
+    #[fixture]
+    fn fake_home() -> TempDir {
+        let fake_home = tempdir().unwrap();
+        env::set_var("HOME", fake_home.path());
+        fs::create_dir(fake_home.path().join(".ssh")).unwrap();
+
+        fake_home
+    }
+
     mod pair {
+        use ssh_key::LineEnding;
+
         use super::*;
 
         #[rstest]
         #[case::dsa(KeyType::Dsa, Algorithm::Dsa)]
         #[case::rsa_default_size(KeyType::Rsa{ size: None }, Algorithm::Rsa{ hash: None })]
         #[case::rsa_custom_size(KeyType::Rsa{ size: Some(2048) }, Algorithm::Rsa{ hash: None })]
+        #[case::ecdsa_p256(KeyType::Ecdsa{ curve: EcdsaCurve::NistP256 }, Algorithm::Ecdsa{ curve: EcdsaCurve::NistP256 })]
+        #[case::ecdsa_p384(KeyType::Ecdsa{ curve: EcdsaCurve::NistP384 }, Algorithm::Ecdsa{ curve: EcdsaCurve::NistP384 })]
+        #[case::ecdsa_p521(KeyType::Ecdsa{ curve: EcdsaCurve::NistP521 }, Algorithm::Ecdsa{ curve: EcdsaCurve::NistP521 })]
         #[case::ed25519(KeyType::Ed25519, Algorithm::Ed25519)]
         fn ok(#[case] key_type: KeyType, #[case] alg: Algorithm) {
-            let (private, public) = ssh::key::pair(EMAIL, &key_type).unwrap();
+            let (private, public) = ssh::key::pair(EMAIL, &key_type, &Policy::default().allow_dsa()).unwrap();
 
             assert_that!(private.algorithm()).is_equal_to(alg);
             assert_that!(public.key_data()).is_equal_to(PublicKey::from(private).key_data());
@@ -132,22 +311,216 @@ mod test {
         fn err() {
             let size = 2047;
             let key_type = KeyType::Rsa { size: Some(size) };
-            let err = ssh::key::pair(EMAIL, &key_type).unwrap_err();
+            let err = ssh::key::pair(EMAIL, &key_type, &Policy::default().allow_dsa()).unwrap_err();
             let expected_err = Error::InvalidRsaLength(size);
 
             assert_that!(format!("{err}")).is_equal_to(format!("{expected_err}"));
         }
+
+        #[rstest]
+        #[case::p256(EcdsaCurve::NistP256)]
+        #[case::p384(EcdsaCurve::NistP384)]
+        #[case::p521(EcdsaCurve::NistP521)]
+        fn ecdsa_readable(#[case] curve: EcdsaCurve) {
+            // roughly half of P-256 and P-384 keys need no padding, so a few rounds are enough to hit one
+            for _ in 0..8 {
+                let (private, _) = ssh::key::pair(EMAIL, &KeyType::Ecdsa { curve }, &Policy::default()).unwrap();
+                let encrypted = ssh::key::encrypt(&private, "passphrase").unwrap();
+
+                let read = PrivateKey::from_openssh(&*private.to_openssh(LineEnding::LF).unwrap()).unwrap();
+                let read_encrypted = PrivateKey::from_openssh(&*encrypted.to_openssh(LineEnding::LF).unwrap()).unwrap();
+
+                assert_that!(read).is_equal_to(&private);
+                assert_that!(read_encrypted.decrypt("passphrase").unwrap()).is_equal_to(&private);
+            }
+        }
+
+        #[test]
+        fn dsa_refused_by_default() {
+            let err = ssh::key::pair(EMAIL, &KeyType::Dsa, &Policy::default()).unwrap_err();
+            let expected_err = Error::PolicyViolation(Violation::NotAllowed("dsa".to_string()));
+
+            assert_that!(format!("{err}")).is_equal_to(format!("{expected_err}"));
+        }
+    }
+
+    mod encrypt {
+        use std::path::Path;
+
+        use spectral::prelude::BooleanAssertions;
+
+        use super::*;
+
+        const PASSPHRASE: &str = "passphrase";
+
+        #[test]
+        fn ok() {
+            let (private, _) = ssh::key::pair(EMAIL, &KeyType::Ed25519, &Policy::default()).unwrap();
+
+            let encrypted = ssh::key::encrypt(&private, PASSPHRASE).unwrap();
+
+            assert_that!(encrypted.is_encrypted()).is_true();
+            assert_that!(encrypted.decrypt(PASSPHRASE).unwrap()).is_equal_to(private);
+        }
+
+        #[rstest]
+        fn public_from_encrypted(_fake_home: TempDir) {
+            let (private, public) = ssh::key::pair(EMAIL, &KeyType::Ed25519, &Policy::default()).unwrap();
+            let encrypted = ssh::key::encrypt(&private, PASSPHRASE).unwrap();
+            ssh::key::write_private_file(Path::new(&ssh::key::path_private(PROFILE_NAME)), &encrypted).unwrap();
+
+            let regenerated = ssh::key::public_from_private(PROFILE_NAME, EMAIL).unwrap();
+
+            assert_that!(regenerated).is_equal_to(public);
+            let written = PrivateKey::read_openssh_file(Path::new(&ssh::key::path_private(PROFILE_NAME))).unwrap();
+            assert_that!(written.is_encrypted()).is_true();
+        }
+    }
+
+    mod retire {
+        use std::path::Path;
+
+        use spectral::prelude::OptionAssertions;
+
+        use super::*;
+
+        #[test]
+        fn keeps_both_keys() {
+            let fake_home = tempdir().unwrap();
+            let private = fake_home.path().join("id_test");
+            fs::write(&private, "private").unwrap();
+            fs::write(fake_home.path().join("id_test.pub"), "public").unwrap();
+
+            let retired = ssh::key::retire(&private, 1_700_000_000).unwrap();
+
+            let expected = fake_home.path().join("id_test.1700000000.old");
+            assert_that!(retired).is_some().is_equal_to(&expected);
+            assert_that!(private).does_not_exist();
+            assert_that!(fs::read_to_string(&expected).unwrap().as_str()).is_equal_to("private");
+            assert_that!(fs::read_to_string(fake_home.path().join("id_test.1700000000.old.pub")).unwrap().as_str())
+                .is_equal_to("public");
+        }
+
+        #[test]
+        fn keeps_certificate() {
+            let fake_home = tempdir().unwrap();
+            let private = fake_home.path().join("id_test");
+            fs::write(&private, "private").unwrap();
+            fs::write(fake_home.path().join("id_test-cert.pub"), "certificate").unwrap();
+
+            ssh::key::retire(&private, 1_700_000_000).unwrap();
+
+            assert_that!(fake_home.path().join("id_test-cert.pub")).does_not_exist();
+            assert_that!(fs::read_to_string(fake_home.path().join("id_test.1700000000.old-cert.pub")).unwrap().as_str())
+                .is_equal_to("certificate");
+        }
+
+        #[test]
+        fn no_keys() {
+            let fake_home = tempdir().unwrap();
+
+            assert_that!(ssh::key::retire(&fake_home.path().join("id_test"), 1).unwrap()).is_none();
+            assert_that!(Path::new(&fake_home.path().join("id_test.1.old"))).does_not_exist();
+        }
+    }
+
+    mod import {
+        use std::path::Path;
+
+        use spectral::prelude::ResultAssertions;
+        use ssh_key::LineEnding;
+
+        use super::*;
+
+        fn existing_key(fake_home: &TempDir) -> (PrivateKey, std::path::PathBuf) {
+            let private = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
+            let path = fake_home.path().join("existing");
+            private.write_openssh_file(&path, LineEnding::LF).unwrap();
+
+            (private, path)
+        }
+
+        #[rstest]
+        fn ok(fake_home: TempDir) {
+            let (private, path) = existing_key(&fake_home);
+
+            ssh::key::import(PROFILE_NAME, EMAIL, &path, false).unwrap();
+
+            assert_that!(fs::read(ssh::key::path_private(PROFILE_NAME)).unwrap()).is_equal_to(fs::read(&path).unwrap());
+            let public = PublicKey::read_openssh_file(Path::new(&ssh::key::path_public(PROFILE_NAME))).unwrap();
+            assert_that!(public.key_data()).is_equal_to(private.public_key().key_data());
+            assert_that!(public.comment()).is_equal_to(EMAIL);
+        }
+
+        #[rstest]
+        fn not_a_key(fake_home: TempDir) {
+            let path = fake_home.path().join("not_a_key");
+            fs::write(&path, "not a key").unwrap();
+
+            assert_that!(ssh::key::import(PROFILE_NAME, EMAIL, &path, false)).is_err();
+            assert_that!(fake_home.path().join(format!(".ssh/id_{PROFILE_NAME}"))).does_not_exist();
+        }
+
+        #[rstest]
+        fn adopt_in_place(fake_home: TempDir) {
+            let (private, path) = existing_key(&fake_home);
+
+            let (adopted, _) = ssh::key::adopt(&path, EMAIL).unwrap();
+
+            assert_that!(adopted).is_equal_to(path.canonicalize().unwrap());
+            let public = PublicKey::read_openssh_file(&fake_home.path().join("existing.pub")).unwrap();
+            assert_that!(public.key_data()).is_equal_to(private.public_key().key_data());
+            assert_that!(public.comment()).is_equal_to(EMAIL);
+            assert_that!(fake_home.path().join(format!(".ssh/id_{PROFILE_NAME}"))).does_not_exist();
+        }
+
+        #[rstest]
+        fn adopt_keeps_public_key(fake_home: TempDir) {
+            let (_, path) = existing_key(&fake_home);
+            fs::write(fake_home.path().join("existing.pub"), "public").unwrap();
+
+            ssh::key::adopt(&path, EMAIL).unwrap();
+
+            assert_that!(fs::read_to_string(fake_home.path().join("existing.pub")).unwrap())
+                .is_equal_to("public".to_string());
+        }
+
+        #[rstest]
+        fn existing_keys(fake_home: TempDir) {
+            let (_, path) = existing_key(&fake_home);
+            fs::write(ssh::key::path_public(PROFILE_NAME), "").unwrap();
+
+            let err = ssh::key::import(PROFILE_NAME, EMAIL, &path, false).unwrap_err();
+
+            assert_that!(err.to_string()).is_equal_to(Error::KeyPairExists.to_string());
+            assert_that!(ssh::key::import(PROFILE_NAME, EMAIL, &path, true)).is_ok();
+        }
+
+        #[rstest]
+        fn public_key_not_written(fake_home: TempDir) {
+            let (_, path) = existing_key(&fake_home);
+            let private_path = ssh::key::path_private(PROFILE_NAME);
+            fs::write(&private_path, "previous").unwrap();
+            // a directory can't be replaced by the public key
+            fs::create_dir(ssh::key::path_public(PROFILE_NAME)).unwrap();
+
+            assert_that!(ssh::key::import(PROFILE_NAME, EMAIL, &path, true)).is_err();
+
+            assert_that!(fs::read_to_string(&private_path).unwrap()).is_equal_to("previous".to_string());
+            for written in [private_path.clone(), ssh::key::path_public(PROFILE_NAME)] {
+                assert_that!(ssh::path_temporary(Path::new(&written))).does_not_exist();
+            }
+        }
     }
 
     #[test]
-    fn write_private() {
+    fn write_private_file() {
         let fake_home = tempdir().unwrap();
-        env::set_var("HOME", fake_home.path());
-        fs::create_dir(fake_home.path().join(".ssh")).unwrap();
+        let path = fake_home.path().join(format!("id_{PROFILE_NAME}"));
         let private = PrivateKey::random(&mut thread_rng(), Algorithm::Ed25519).unwrap();
 
-        ssh::key::write_private(PROFILE_NAME, &private).unwrap();
+        ssh::key::write_private_file(&path, &private).unwrap();
 
-        assert_that!(fake_home.path().join(format!(".ssh/id_{PROFILE_NAME}"))).exists();
+        assert_that!(PrivateKey::read_openssh_file(&path).unwrap()).is_equal_to(private);
     }
 }
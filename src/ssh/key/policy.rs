@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use ssh_key::{Algorithm, EcdsaCurve, PublicKey};
+
+use crate::ssh::error::Error;
+use crate::ssh::key::r#type::KeyType;
+use crate::ssh::key::{DEFAULT_RSA_SIZE, MIN_RSA_SIZE};
+use crate::ssh::Result;
+
+/// Rules ssh keys of profiles have to follow, kept in the `[key_policy]` section of settings
+///
+/// Algorithms are named like key types of `--key-type`: dsa, rsa, ecdsa-p256, ecdsa-p384, ecdsa-p521, ed25519.
+/// DSA is disabled in modern OpenSSH, so it's not allowed unless added to `allowed` explicitly.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Policy {
+    /// Minimum size of RSA keys in bits, it can't go below [`MIN_RSA_SIZE`]
+    pub min_rsa_size: usize,
+    /// Algorithms keys can use
+    pub allowed: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_rsa_size: MIN_RSA_SIZE,
+            allowed: ["rsa", "ecdsa-p256", "ecdsa-p384", "ecdsa-p521", "ed25519"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Reason a key doesn't follow the [`Policy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    NotAllowed(String),
+    RsaTooShort { size: usize, min: usize },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::NotAllowed(algorithm) => write!(f, "{algorithm} keys aren't allowed by key policy"),
+            Violation::RsaTooShort { size, min } => {
+                write!(f, "RSA key has {size} bits, key policy requires at least {min}")
+            }
+        }
+    }
+}
+
+impl Policy {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The same policy, only allowing DSA keys as well
+    pub fn allow_dsa(mut self) -> Self {
+        if !self.allowed.iter().any(|a| a == "dsa") {
+            self.allowed.push("dsa".to_string());
+        }
+        self
+    }
+
+    /// Checks if keys of `key_type` can be generated
+    pub fn check(&self, key_type: &KeyType) -> Result<()> {
+        let size = match key_type {
+            KeyType::Rsa { size } => Some(size.unwrap_or(DEFAULT_RSA_SIZE)),
+            _ => None,
+        };
+        match self.violation(&key_type.algorithm(), size) {
+            Some(violation) => Err(Error::PolicyViolation(violation)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks an existing key, returning the way it violates the policy
+    pub fn audit(&self, key: &PublicKey) -> Option<Violation> {
+        let size = key.key_data().rsa()
+            .and_then(|rsa| rsa.n.as_positive_bytes())
+            .map(|n| n.len() * 8 - n.first().map_or(0, |b| b.leading_zeros() as usize));
+        self.violation(&key.algorithm(), size)
+    }
+
+    fn violation(&self, algorithm: &Algorithm, rsa_size: Option<usize>) -> Option<Violation> {
+        let name = algorithm_name(algorithm);
+        if !self.allowed.iter().any(|a| a.eq_ignore_ascii_case(&name)) {
+            return Some(Violation::NotAllowed(name));
+        }
+        let min = self.min_rsa_size.max(MIN_RSA_SIZE);
+        rsa_size
+            .filter(|&size| size < min)
+            .map(|size| Violation::RsaTooShort { size, min })
+    }
+}
+
+fn algorithm_name(algorithm: &Algorithm) -> String {
+    match algorithm {
+        Algorithm::Dsa => "dsa".to_string(),
+        Algorithm::Rsa { .. } => "rsa".to_string(),
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP256 } => "ecdsa-p256".to_string(),
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP384 } => "ecdsa-p384".to_string(),
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP521 } => "ecdsa-p521".to_string(),
+        Algorithm::Ed25519 => "ed25519".to_string(),
+        other => other.as_str().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::thread_rng;
+    use rstest::rstest;
+    use spectral::assert_that;
+    use spectral::prelude::{BooleanAssertions, OptionAssertions};
+    use ssh_key::private::RsaKeypair;
+    use ssh_key::PrivateKey;
+
+    use super::*;
+
+    #[rstest]
+    #[case::dsa(KeyType::Dsa, false)]
+    #[case::rsa(KeyType::Rsa { size: None }, true)]
+    #[case::ecdsa(KeyType::Ecdsa { curve: EcdsaCurve::NistP384 }, true)]
+    #[case::ed25519(KeyType::Ed25519, true)]
+    fn default_policy(#[case] key_type: KeyType, #[case] allowed: bool) {
+        assert_that!(Policy::default().check(&key_type).is_ok()).is_equal_to(allowed);
+    }
+
+    #[test]
+    fn dsa_override() {
+        assert_that!(Policy::default().allow_dsa().check(&KeyType::Dsa).is_ok()).is_true();
+    }
+
+    #[test]
+    fn restricted_algorithms() {
+        let policy = Policy { allowed: vec!["ECDSA-P384".to_string()], ..Policy::default() };
+
+        assert_that!(policy.check(&KeyType::Ecdsa { curve: EcdsaCurve::NistP384 }).is_ok()).is_true();
+        assert_that!(policy.check(&KeyType::Ed25519).is_ok()).is_false();
+    }
+
+    #[rstest]
+    #[case::default_size(None, false)]
+    #[case::too_short(Some(3072), false)]
+    #[case::long_enough(Some(4096), true)]
+    fn min_rsa_size(#[case] size: Option<usize>, #[case] allowed: bool) {
+        let policy = Policy { min_rsa_size: 4096, ..Policy::default() };
+
+        assert_that!(policy.check(&KeyType::Rsa { size }).is_ok()).is_equal_to(allowed);
+    }
+
+    #[test]
+    fn audit_rsa_size() {
+        let private = PrivateKey::from(RsaKeypair::random(&mut thread_rng(), 2048).unwrap());
+        let policy = Policy { min_rsa_size: 3072, ..Policy::default() };
+
+        assert_that!(policy.audit(private.public_key())).is_some()
+            .is_equal_to(Violation::RsaTooShort { size: 2048, min: 3072 });
+        assert_that!(Policy::default().audit(private.public_key())).is_none();
+    }
+}
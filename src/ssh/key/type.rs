@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter};
 
+use ssh_key::{Algorithm, EcdsaCurve};
+
 use crate::ssh::error::Error;
 use crate::ssh::key::DEFAULT_RSA_SIZE;
 use crate::ssh::Result;
@@ -9,6 +11,7 @@ use crate::ssh::Result;
 pub enum KeyType {
     Dsa,
     Rsa { size: Option<usize> },
+    Ecdsa { curve: EcdsaCurve },
     Ed25519,
 }
 
@@ -25,10 +28,24 @@ impl KeyType {
                 let size = s[3..].parse::<usize>().ok();
                 Ok(Self::Rsa { size })
             }
+            // same default curve as ssh-keygen
+            "ecdsa" | "ecdsa-p256" => Ok(Self::Ecdsa { curve: EcdsaCurve::NistP256 }),
+            "ecdsa-p384" => Ok(Self::Ecdsa { curve: EcdsaCurve::NistP384 }),
+            "ecdsa-p521" => Ok(Self::Ecdsa { curve: EcdsaCurve::NistP521 }),
             "ed25519" => Ok(Self::Ed25519),
             s => Err(Error::UnknownKeyType(s.to_string())),
         }
     }
+
+    /// Algorithm of keys with this type
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            KeyType::Dsa => Algorithm::Dsa,
+            KeyType::Rsa { .. } => Algorithm::Rsa { hash: None },
+            KeyType::Ecdsa { curve } => Algorithm::Ecdsa { curve: *curve },
+            KeyType::Ed25519 => Algorithm::Ed25519,
+        }
+    }
 }
 
 impl Display for KeyType {
@@ -39,6 +56,7 @@ impl Display for KeyType {
             match self {
                 KeyType::Dsa => "dsa",
                 KeyType::Rsa { .. } => "rsa",
+                KeyType::Ecdsa { .. } => "ecdsa",
                 KeyType::Ed25519 => "ed25519",
             }
         )
@@ -50,6 +68,11 @@ impl RandomArtHeader for KeyType {
         match self {
             KeyType::Dsa => "DSA 1024".to_string(),
             KeyType::Rsa { size } => format!("RSA {}", size.unwrap_or(DEFAULT_RSA_SIZE)),
+            KeyType::Ecdsa { curve } => match curve {
+                EcdsaCurve::NistP256 => "ECDSA 256",
+                EcdsaCurve::NistP384 => "ECDSA 384",
+                EcdsaCurve::NistP521 => "ECDSA 521",
+            }.to_string(),
             KeyType::Ed25519 => "ED25519".to_string(),
         }
     }
@@ -68,13 +91,17 @@ mod test {
         #[case::dsa("dsa", KeyType::Dsa)]
         #[case::rsa_default("rsa", KeyType::Rsa {size: None})]
         #[case::rsa_valid_size("rsa2048", KeyType::Rsa {size: Some(2048)})]
+        #[case::ecdsa_default("ecdsa", KeyType::Ecdsa {curve: EcdsaCurve::NistP256})]
+        #[case::ecdsa_p256("ecdsa-p256", KeyType::Ecdsa {curve: EcdsaCurve::NistP256})]
+        #[case::ecdsa_p384("ECDSA-P384", KeyType::Ecdsa {curve: EcdsaCurve::NistP384})]
+        #[case::ecdsa_p521("ecdsa-p521", KeyType::Ecdsa {curve: EcdsaCurve::NistP521})]
         #[case::ed25519("ed25519", KeyType::Ed25519)]
         fn ok(#[case] key_type_str: &str, #[case] expected_key_type: KeyType) {
             assert_that!(KeyType::parse(key_type_str).unwrap()).is_equal_to(expected_key_type);
         }
 
         #[rstest]
-        #[case::unknown_key_type("ecdsa")]
+        #[case::unknown_key_type("ecdsa-p224")]
         fn err(#[case] key_type_str: &str) {
             let err_msg = format!("{}", KeyType::parse(key_type_str).unwrap_err());
             let expected_err_msg = format!("{}", Error::UnknownKeyType(key_type_str.to_string()));
@@ -87,6 +114,7 @@ mod test {
     #[case::dsa(KeyType::Dsa, "dsa")]
     #[case::rsa_default_size(KeyType::Rsa{ size: None }, "rsa")]
     #[case::rsa_custom_size(KeyType::Rsa{ size: Some(2048) }, "rsa")]
+    #[case::ecdsa(KeyType::Ecdsa{ curve: EcdsaCurve::NistP384 }, "ecdsa")]
     #[case::ed25519(KeyType::Ed25519, "ed25519")]
     fn display(#[case] key_type: KeyType, #[case] expected_display: &str) {
         let display = format!("{}", key_type);
@@ -98,6 +126,9 @@ mod test {
     #[case::dsa(KeyType::Dsa, "DSA 1024")]
     #[case::rsa_default_size(KeyType::Rsa{ size: None }, "RSA 3072")]
     #[case::rsa_custom_size(KeyType::Rsa {size: Some(2048) }, "RSA 2048")]
+    #[case::ecdsa_p256(KeyType::Ecdsa {curve: EcdsaCurve::NistP256 }, "ECDSA 256")]
+    #[case::ecdsa_p384(KeyType::Ecdsa {curve: EcdsaCurve::NistP384 }, "ECDSA 384")]
+    #[case::ecdsa_p521(KeyType::Ecdsa {curve: EcdsaCurve::NistP521 }, "ECDSA 521")]
     #[case::ed25519(KeyType::Ed25519, "ED25519")]
     fn random_art_header(#[case] key_type: KeyType, #[case] expected_header: &str) {
         assert_that!(key_type.random_art_header()).is_equal_to(expected_header.to_string());
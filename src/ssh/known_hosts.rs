@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::Path;
+
+use ssh_key::known_hosts::{Entry, HostPatterns, KnownHosts};
+
+use crate::ssh::error::Error;
+use crate::ssh::key::restrict_permissions;
+use crate::ssh::{write_through_temporary, Result};
+
+/// Parses entries of a known_hosts file, e.g. output of `ssh-keyscan`, skipping comments and empty lines
+pub(crate) fn parse(content: &str) -> Result<Vec<Entry>> {
+    let entries = KnownHosts::new(content).collect::<std::result::Result<_, _>>()?;
+
+    Ok(entries)
+}
+
+/// Reads entries of known_hosts file at `path`, there are none if it doesn't exist
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| Error::Io(e, path.to_path_buf()))?;
+
+    parse(&content)
+}
+
+/// Appends `entries` to known_hosts file at `path`, creating it if needed.
+/// Entries the file already has (regardless of key comment) are skipped.
+///
+/// Returns entries that were added.
+///
+/// ```
+/// let entries = parse("github.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl")
+///     .expect("Invalid known_hosts entry");
+/// add(Path::new("/home/user/.config/g-profiles/work.known_hosts"), entries).expect("Can't add known hosts");
+/// ```
+pub(crate) fn add(path: &Path, entries: Vec<Entry>) -> Result<Vec<Entry>> {
+    let mut known = read(path)?;
+    let mut added = vec![];
+    for entry in entries {
+        if !known.iter().any(|existing| same(existing, &entry)) {
+            known.push(entry.clone());
+            added.push(entry);
+        }
+    }
+    if !added.is_empty() || !path.exists() {
+        write(path, &known)?;
+    }
+
+    Ok(added)
+}
+
+/// Removes entries for `host` from known_hosts file at `path`, see [`matches`].
+///
+/// Returns entries that were removed.
+pub(crate) fn remove(path: &Path, host: &str) -> Result<Vec<Entry>> {
+    let (removed, kept): (Vec<_>, Vec<_>) = read(path)?
+        .into_iter()
+        .partition(|entry| matches(entry, host));
+    if !removed.is_empty() {
+        write(path, &kept)?;
+    }
+
+    Ok(removed)
+}
+
+/// Whether `entry` lists `host` (or `[host]:port`) as one of its patterns.
+/// Hashed host names can't be matched without the hostname's hash, so they never match.
+pub(crate) fn matches(entry: &Entry, host: &str) -> bool {
+    match entry.host_patterns() {
+        HostPatterns::Patterns(patterns) => patterns.iter().any(|pattern| {
+            pattern == host || pattern.strip_prefix(&format!("[{host}]:")).is_some()
+        }),
+        HostPatterns::HashedName { .. } => false,
+    }
+}
+
+fn same(a: &Entry, b: &Entry) -> bool {
+    a.marker() == b.marker()
+        && a.host_patterns() == b.host_patterns()
+        && a.public_key().key_data() == b.public_key().key_data()
+}
+
+/// Replaces known_hosts file at `path` with `entries`, readable and writable only by its owner
+fn write(path: &Path, entries: &[Entry]) -> Result<()> {
+    let content = entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.to_string()))
+        .collect::<String>();
+    write_through_temporary(path, |temporary| {
+        fs::write(temporary, content)
+            .map_err(|e| Error::Io(e, temporary.to_path_buf()))?;
+        restrict_permissions(temporary)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::assert_that;
+    use spectral::prelude::{BooleanAssertions, PathAssertions, VecAssertions};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    const GITHUB: &str =
+        "github.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+    const GITLAB: &str =
+        "gitlab.com,[gitlab.com]:2222 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAfuCHKVTjquxvt6CM6tdG4SLp1Btn/nOeHHE5UOzRdf";
+
+    #[test]
+    fn parse_skips_comments() {
+        let entries = parse(&format!("# github.com:22 SSH-2.0-babeld\n\n{GITHUB} comment\n{GITLAB}\n")).unwrap();
+
+        assert_that!(entries).has_length(2);
+        assert_that!(entries[0].to_string()).is_equal_to(format!("{GITHUB} comment"));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_that!(parse("github.com not-a-key").is_err()).is_true();
+    }
+
+    #[test]
+    fn add_skips_known() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work.known_hosts");
+
+        let added = add(&path, parse(GITHUB).unwrap()).unwrap();
+        assert_that!(added).has_length(1);
+        let added = add(&path, parse(&format!("{GITHUB} other comment\n{GITLAB}")).unwrap()).unwrap();
+
+        assert_that!(added).is_equal_to(parse(GITLAB).unwrap());
+        assert_that!(fs::read_to_string(&path).unwrap()).is_equal_to(format!("{GITHUB}\n{GITLAB}\n"));
+    }
+
+    #[test]
+    fn add_nothing_creates_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work.known_hosts");
+
+        add(&path, vec![]).unwrap();
+
+        assert_that!(path).exists();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn private_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work.known_hosts");
+
+        add(&path, parse(GITHUB).unwrap()).unwrap();
+
+        assert_that!(fs::metadata(&path).unwrap().permissions().mode() & 0o777).is_equal_to(0o600);
+        assert_that!(crate::ssh::path_temporary(&path)).does_not_exist();
+    }
+
+    #[test]
+    fn remove_host() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work.known_hosts");
+        fs::write(&path, format!("{GITHUB}\n{GITLAB}\n")).unwrap();
+
+        let removed = remove(&path, "gitlab.com").unwrap();
+
+        assert_that!(removed).is_equal_to(parse(GITLAB).unwrap());
+        assert_that!(fs::read_to_string(&path).unwrap()).is_equal_to(format!("{GITHUB}\n"));
+        assert_that!(remove(&path, "example.com").unwrap()).is_empty();
+    }
+
+    #[test]
+    fn match_patterns() {
+        let gitlab = &parse(GITLAB).unwrap()[0];
+        let hashed = &parse("|1|JfKTdBh7rNbXkVAQCRp4OQoPfmI=|USECr3SWf1JUPsms5AqfD5QfxkM= ssh-ed25519 \
+            AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl").unwrap()[0];
+
+        assert_that!(matches(gitlab, "gitlab.com")).is_true();
+        assert_that!(matches(gitlab, "gitlab")).is_false();
+        assert_that!(matches(hashed, "github.com")).is_false();
+    }
+}
@@ -1,9 +1,15 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::ssh::error::Error;
 
+#[cfg(unix)]
+pub mod agent;
+pub mod cert;
+pub mod config;
 pub mod error;
 pub mod key;
+pub mod known_hosts;
 
 type Result<T> = std::result::Result<T, error::Error>;
 
@@ -20,6 +26,34 @@ pub(crate) fn try_regenerate_pair(profile_name: &str, email: &str, force: bool)
     Ok(())
 }
 
+/// Path of the temporary file `path` is written through, next to it so that it can be renamed over it
+pub(crate) fn path_temporary(path: &Path) -> PathBuf {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".g-tmp");
+
+    PathBuf::from(temporary)
+}
+
+/// Replaces file at `path` with a temporary file filled by `write`, so that a failure never leaves it half-written.
+/// The temporary file is removed if writing or replacing fails.
+///
+/// ```
+/// write_through_temporary(Path::new("/home/user/.ssh/id_work.pub"), |temporary| key::write_public_file(temporary, &public))
+///     .expect("Can't write public key");
+/// ```
+pub(crate) fn write_through_temporary(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let temporary = path_temporary(path);
+    let written = write(&temporary).and_then(|_| {
+        fs::rename(&temporary, path).map_err(|e| Error::Io(e, path.to_path_buf()))
+    });
+    if written.is_err() {
+        // best effort, the original error is what matters
+        let _ = fs::remove_file(&temporary);
+    }
+
+    written
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -48,11 +82,11 @@ mod test {
         }
 
         fn priv_path<P: AsRef<Path>>(home: P) -> PathBuf {
-            home.as_ref().join(&format!(".ssh/id_{PROFILE_NAME}"))
+            home.as_ref().join(format!(".ssh/id_{PROFILE_NAME}"))
         }
 
         fn pub_path<P: AsRef<Path>>(home: P) -> PathBuf {
-            home.as_ref().join(&format!(".ssh/id_{PROFILE_NAME}.pub"))
+            home.as_ref().join(format!(".ssh/id_{PROFILE_NAME}.pub"))
         }
 
         fn file_hash<P: AsRef<Path>>(path: P) -> String {